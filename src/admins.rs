@@ -0,0 +1,105 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use r2d2_redis::redis::{self, ConnectionLike, PipelineCommands, RedisResult};
+
+/// the key of the set containing every admin, which is what permission checks look at
+pub const ADMINS_KEY: &str = "admins";
+
+/// the key of the set containing the admins listed in the configuration, along with the owner
+const PINNED_KEY: &str = "admins-pinned";
+
+/// the key of the set containing the admins added with the `admin` command
+const RUNTIME_KEY: &str = "admins-runtime";
+
+/// the outcome of trying to take away a user's admin status
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Removal {
+    Removed,
+    NotAdmin,
+
+    /// the user is listed in the configuration, so they have to be removed from there instead
+    Pinned,
+}
+
+/// replaces the admins pinned by the configuration and rebuilds the admin set out of them and
+/// the admins added at runtime, returning the users who are no longer admins
+pub fn reconcile<C: ConnectionLike>(database: &mut C, pinned: &[u64]) -> RedisResult<Vec<u64>> {
+    let mut pipeline = redis::pipe();
+    pipeline.atomic().del(PINNED_KEY).ignore();
+    if !pinned.is_empty() {
+        pipeline.sadd(PINNED_KEY, pinned).ignore();
+    }
+    let (removed,) = pipeline
+        .sdiff(&[ADMINS_KEY, PINNED_KEY, RUNTIME_KEY])
+        .sunionstore(&[ADMINS_KEY][..], &[PINNED_KEY, RUNTIME_KEY][..])
+        .ignore()
+        .query::<(Vec<u64>,)>(database)?;
+    Ok(removed)
+}
+
+/// makes a user an admin, returning whether they weren't one already. users who are already
+/// admins aren't added to the runtime set, so that unpinning them later takes effect
+pub fn add<C: ConnectionLike>(database: &mut C, user_id: u64) -> RedisResult<bool> {
+    let (pinned, admin) = redis::pipe()
+        .sismember(PINNED_KEY, user_id)
+        .sismember(ADMINS_KEY, user_id)
+        .query::<(bool, bool)>(database)?;
+    if pinned || admin {
+        return Ok(false);
+    }
+
+    redis::pipe()
+        .atomic()
+        .sadd(RUNTIME_KEY, user_id)
+        .sadd(ADMINS_KEY, user_id)
+        .query::<()>(database)?;
+    Ok(true)
+}
+
+/// takes away the admin status of a user added at runtime
+pub fn remove<C: ConnectionLike>(database: &mut C, user_id: u64) -> RedisResult<Removal> {
+    let (pinned, admin) = redis::pipe()
+        .sismember(PINNED_KEY, user_id)
+        .sismember(ADMINS_KEY, user_id)
+        .query::<(bool, bool)>(database)?;
+    if pinned {
+        return Ok(Removal::Pinned);
+    } else if !admin {
+        return Ok(Removal::NotAdmin);
+    }
+
+    redis::pipe()
+        .atomic()
+        .srem(RUNTIME_KEY, user_id)
+        .srem(ADMINS_KEY, user_id)
+        .query::<()>(database)?;
+    Ok(Removal::Removed)
+}
+
+/// retrieves the admins pinned by the configuration and the admins added at runtime, each sorted
+/// by id. admins that are both are only listed as pinned
+pub fn list<C: ConnectionLike>(database: &mut C) -> RedisResult<(Vec<u64>, Vec<u64>)> {
+    let (mut pinned, mut runtime) = redis::pipe()
+        .smembers(PINNED_KEY)
+        .sdiff(&[RUNTIME_KEY, PINNED_KEY])
+        .query::<(Vec<u64>, Vec<u64>)>(database)?;
+    pinned.sort_unstable();
+    runtime.sort_unstable();
+    Ok((pinned, runtime))
+}
@@ -0,0 +1,124 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use log::error;
+use r2d2_redis::redis::{self, Commands, ConnectionLike, ErrorKind, RedisError, RedisResult};
+use serde::{Deserialize, Serialize};
+
+/// the key of the hash holding every appeal, keyed by id
+const APPEALS_KEY: &str = "appeals";
+
+/// the key of the counter used to number appeals
+const NEXT_ID_KEY: &str = "appeals-next-id";
+
+/// the key that exists while a user has to wait before appealing their ban from a network again
+fn cooldown_key(network: &str, user_id: u64) -> String {
+    format!("network-{}-appeal-cooldown-{}", network, user_id)
+}
+
+/// what has been decided about an appeal
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Pending,
+    Accepted,
+    Denied,
+}
+
+/// a banned user's request to be unbanned from a network
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Appeal {
+    pub id: u64,
+    pub user_id: u64,
+    pub network: String,
+    pub message: String,
+
+    /// the unix timestamp of when the appeal was made
+    pub created_at: i64,
+    pub status: Status,
+
+    /// the admin who decided on the appeal
+    pub moderator_id: Option<u64>,
+
+    /// the note the admin left for the user when deciding on the appeal
+    pub note: Option<String>,
+}
+
+/// saves a new appeal, giving it an id
+pub fn create<C: ConnectionLike>(database: &mut C, appeal: &mut Appeal) -> RedisResult<()> {
+    appeal.id = database.incr(NEXT_ID_KEY, 1)?;
+    save(database, appeal)
+}
+
+/// saves changes made to an appeal
+pub fn save<C: ConnectionLike>(database: &mut C, appeal: &Appeal) -> RedisResult<()> {
+    match rmp_serde::to_vec_named(appeal) {
+        Ok(encoded) => database.hset(APPEALS_KEY, appeal.id, encoded),
+        Err(msg) => {
+            error!("unable to encode an appeal: {:?}", msg);
+            Err(RedisError::from((
+                ErrorKind::TypeError,
+                "unable to encode an appeal",
+            )))
+        }
+    }
+}
+
+/// retrieves an appeal by its id
+pub fn get<C: ConnectionLike>(database: &mut C, id: u64) -> RedisResult<Option<Appeal>> {
+    Ok(database
+        .hget::<&str, u64, Option<Vec<u8>>>(APPEALS_KEY, id)?
+        .and_then(|encoded| match rmp_serde::from_slice(&encoded) {
+            Ok(appeal) => Some(appeal),
+            Err(msg) => {
+                error!("unable to decode an appeal: {:?}", msg);
+                None
+            }
+        }))
+}
+
+/// starts the cooldown of a user's appeals of their ban from a network. if it has already
+/// started, the number of seconds left until it ends is returned instead
+pub fn start_cooldown<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    cooldown: u64,
+) -> RedisResult<Option<i64>> {
+    let key = cooldown_key(network, user_id);
+    let started: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(cooldown)
+        .query(database)?;
+
+    match started {
+        Some(_) => Ok(None),
+        None => Ok(Some(database.ttl(&key)?)),
+    }
+}
+
+/// ends the cooldown of a user's appeals of their ban from a network early
+pub fn end_cooldown<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<()> {
+    database.del(cooldown_key(network, user_id))
+}
@@ -0,0 +1,449 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use log::error;
+use r2d2_redis::redis::{self, ConnectionLike, RedisResult};
+use serde::{Deserialize, Serialize};
+use serenity::{model::channel::Message, prelude::*};
+use std::fmt;
+
+use crate::{get_db_handle, modlog, types};
+
+/// the key of the stream holding the audit log
+const AUDIT_KEY: &str = "audit";
+
+/// how many entries are read from the stream at a time when searching it
+const SCAN_BATCH: usize = 100;
+
+/// something that changed the state of the bot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Enable,
+    Disable,
+    Configure,
+    Ban,
+    Unban,
+    GuildBan,
+    GuildUnban,
+    Mute,
+    Unmute,
+    Warn,
+    Pardon,
+    Purge,
+    Notify,
+    Report,
+    Dismiss,
+    Appeal,
+    Deny,
+    Block,
+    Unblock,
+    GuildBlock,
+    GuildUnblock,
+    AdminAdd,
+    AdminRemove,
+    RoleSet,
+    RoleRemove,
+}
+
+impl Action {
+    /// every action, in the order they are listed to users
+    pub const ALL: &'static [Action] = &[
+        Action::Enable,
+        Action::Disable,
+        Action::Configure,
+        Action::Ban,
+        Action::Unban,
+        Action::GuildBan,
+        Action::GuildUnban,
+        Action::Mute,
+        Action::Unmute,
+        Action::Warn,
+        Action::Pardon,
+        Action::Purge,
+        Action::Notify,
+        Action::Report,
+        Action::Dismiss,
+        Action::Appeal,
+        Action::Deny,
+        Action::Block,
+        Action::Unblock,
+        Action::GuildBlock,
+        Action::GuildUnblock,
+        Action::AdminAdd,
+        Action::AdminRemove,
+        Action::RoleSet,
+        Action::RoleRemove,
+    ];
+
+    /// the name of the action, which matches the command that performs it
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Enable => "enable",
+            Action::Disable => "disable",
+            Action::Configure => "configure",
+            Action::Ban => "ban",
+            Action::Unban => "unban",
+            Action::GuildBan => "guildban",
+            Action::GuildUnban => "guildunban",
+            Action::Mute => "mute",
+            Action::Unmute => "unmute",
+            Action::Warn => "warn",
+            Action::Pardon => "pardon",
+            Action::Purge => "purge",
+            Action::Notify => "notify",
+            Action::Report => "report",
+            Action::Dismiss => "dismiss",
+            Action::Appeal => "appeal",
+            Action::Deny => "deny",
+            Action::Block => "block",
+            Action::Unblock => "unblock",
+            Action::GuildBlock => "guildblock",
+            Action::GuildUnblock => "guildunblock",
+            Action::AdminAdd => "adminadd",
+            Action::AdminRemove => "adminremove",
+            Action::RoleSet => "roleset",
+            Action::RoleRemove => "roleremove",
+        }
+    }
+
+    /// looks an action up by its name
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name().eq_ignore_ascii_case(name))
+    }
+
+    /// checks if the action is taken by moderators against users or servers, and is therefore
+    /// posted to the mod-log. blocks only affect the server that made them, so they aren't
+    pub fn is_moderation(self) -> bool {
+        !matches!(
+            self,
+            Action::Enable
+                | Action::Disable
+                | Action::Configure
+                | Action::Report
+                | Action::Appeal
+                | Action::Block
+                | Action::Unblock
+                | Action::GuildBlock
+                | Action::GuildUnblock
+                | Action::AdminAdd
+                | Action::AdminRemove
+                | Action::RoleSet
+                | Action::RoleRemove
+        )
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+/// who or what an action was taken against
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    User(u64),
+    Guild(u64),
+    Channel(u64),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::User(id) => write!(formatter, "<@{}> (`{}`)", id, id),
+            Target::Guild(id) => write!(formatter, "server `{}`", id),
+            Target::Channel(id) => write!(formatter, "<#{}>", id),
+        }
+    }
+}
+
+/// an action about to be recorded
+pub struct Entry<'a> {
+    pub action: Action,
+    pub target: Option<Target>,
+
+    /// the network the action was taken in. actions without one concern every network
+    pub network: Option<&'a str>,
+    pub reason: Option<&'a str>,
+}
+
+/// where an action came from
+pub struct Source {
+    pub actor_id: u64,
+
+    /// the guild the action was taken from, if it wasn't taken in a direct message
+    pub guild_id: Option<u64>,
+
+    /// the channel and message that triggered the action
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl Source {
+    /// the source of actions the bot takes on its own, such as lifting expired punishments.
+    /// these are attributed to the bot and have no message behind them
+    pub fn automatic(context: &Context) -> Source {
+        Source {
+            actor_id: context.cache.read().user.id.0,
+            guild_id: None,
+            channel_id: 0,
+            message_id: 0,
+        }
+    }
+
+    /// a link to the message that triggered the action, if it can be linked to
+    pub fn link(&self) -> Option<String> {
+        self.guild_id.map(|guild_id| {
+            format!(
+                "https://discordapp.com/channels/{}/{}/{}",
+                guild_id, self.channel_id, self.message_id
+            )
+        })
+    }
+}
+
+impl From<&Message> for Source {
+    fn from(message: &Message) -> Source {
+        Source {
+            actor_id: message.author.id.0,
+            guild_id: message.guild_id.map(|guild_id| guild_id.0),
+            channel_id: message.channel_id.0,
+            message_id: message.id.0,
+        }
+    }
+}
+
+/// an action as it is stored in the audit log
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Record {
+    pub action: Action,
+    pub actor_id: u64,
+
+    /// the guild the action was taken from, if it wasn't taken in a direct message
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub target: Option<Target>,
+    pub network: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl Record {
+    /// checks if the record involves a user, guild or channel with the given id in any way
+    pub fn involves(&self, id: u64) -> bool {
+        self.actor_id == id
+            || self.guild_id == Some(id)
+            || match self.target {
+                Some(Target::User(target))
+                | Some(Target::Guild(target))
+                | Some(Target::Channel(target)) => target == id,
+                None => false,
+            }
+    }
+}
+
+/// records an action in the audit log. moderation actions are posted to the mod-log as well
+pub fn record(context: &Context, source: &Source, entry: &Entry<'_>) {
+    if entry.action.is_moderation() {
+        modlog::post(context, source, entry);
+    }
+
+    let record = Record {
+        action: entry.action,
+        actor_id: source.actor_id,
+        guild_id: source.guild_id,
+        channel_id: source.channel_id,
+        message_id: source.message_id,
+        target: entry.target,
+        network: entry.network.map(String::from),
+        reason: entry.reason.map(String::from),
+    };
+    let encoded = match rmp_serde::to_vec_named(&record) {
+        Ok(encoded) => encoded,
+        Err(msg) => {
+            error!("unable to encode an audit log entry: {:?}", msg);
+            return;
+        }
+    };
+
+    let retention = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => cfg.database.audit_retention,
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+    if let Err(msg) = redis::cmd("XADD")
+        .arg(AUDIT_KEY)
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(retention)
+        .arg("*")
+        .arg("record")
+        .arg(encoded)
+        .query::<String>(&mut *database)
+    {
+        error!("unable to write to the audit log: {:?}", msg);
+    }
+}
+
+/// the unix timestamp, in milliseconds, encoded in a stream entry id
+fn timestamp_of(id: &str) -> i64 {
+    id.split('-')
+        .next()
+        .and_then(|timestamp| timestamp.parse().ok())
+        .unwrap_or(0)
+}
+
+/// the id that comes right before a stream entry id
+fn preceding(id: &str) -> Option<String> {
+    let mut parts = id.splitn(2, '-');
+    let timestamp = parts.next()?.parse::<u64>().ok()?;
+    let sequence = parts.next()?.parse::<u64>().ok()?;
+
+    match (timestamp, sequence) {
+        (0, 0) => None,
+        (timestamp, 0) => Some(format!("{}-{}", timestamp - 1, u64::MAX)),
+        (timestamp, sequence) => Some(format!("{}-{}", timestamp, sequence - 1)),
+    }
+}
+
+/// the id of the earliest stream entry recorded at or after a unix timestamp
+fn earliest(since: Option<i64>) -> String {
+    // stream ids can't be negative, so anything that early is the same as no limit
+    match since
+        .map(|since| since.saturating_mul(1000))
+        .filter(|since| *since > 0)
+    {
+        Some(since) => format!("{}-0", since),
+        None => String::from("-"),
+    }
+}
+
+/// searches the audit log from newest to oldest, skipping the given number of matching records
+/// and returning up to `count` of the ones after them along with the unix timestamp of each.
+/// records older than `since`, a unix timestamp, are not searched
+pub fn search<C: ConnectionLike>(
+    database: &mut C,
+    filter: impl Fn(&Record) -> bool,
+    since: Option<i64>,
+    skip: usize,
+    count: usize,
+) -> RedisResult<Vec<(i64, Record)>> {
+    let start = earliest(since);
+    let mut end = String::from("+");
+    let mut skipped = 0;
+    let mut found = Vec::with_capacity(count);
+
+    loop {
+        let batch = redis::cmd("XREVRANGE")
+            .arg(AUDIT_KEY)
+            .arg(&end)
+            .arg(&start)
+            .arg("COUNT")
+            .arg(SCAN_BATCH)
+            .query::<Vec<(String, Vec<Vec<u8>>)>>(database)?;
+
+        for (id, fields) in &batch {
+            // entries are written with a single field holding the encoded record
+            let record = match fields.get(1).map(|encoded| rmp_serde::from_slice(encoded)) {
+                Some(Ok(record)) => record,
+                Some(Err(msg)) => {
+                    error!("unable to decode audit log entry {}: {:?}", id, msg);
+                    continue;
+                }
+                None => continue,
+            };
+
+            if !filter(&record) {
+                continue;
+            }
+            if skipped < skip {
+                skipped += 1;
+                continue;
+            }
+
+            found.push((timestamp_of(id) / 1000, record));
+            if found.len() == count {
+                return Ok(found);
+            }
+        }
+
+        if batch.len() < SCAN_BATCH {
+            return Ok(found);
+        }
+        end = match batch.last().and_then(|(id, _)| preceding(id)) {
+            Some(end) => end,
+            None => return Ok(found),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_timestamps_from_ids() {
+        assert_eq!(timestamp_of("1600000000000-3"), 1_600_000_000_000);
+        assert_eq!(timestamp_of("1600000000000"), 1_600_000_000_000);
+        assert_eq!(timestamp_of("garbage"), 0);
+    }
+
+    #[test]
+    fn steps_back_within_a_millisecond() {
+        assert_eq!(
+            preceding("1600000000000-3"),
+            Some(String::from("1600000000000-2"))
+        );
+    }
+
+    #[test]
+    fn steps_back_across_milliseconds() {
+        assert_eq!(
+            preceding("1600000000000-0"),
+            Some(format!("1599999999999-{}", u64::MAX))
+        );
+        assert_eq!(preceding("1-0"), Some(format!("0-{}", u64::MAX)));
+    }
+
+    #[test]
+    fn stops_at_the_first_id() {
+        assert_eq!(preceding("0-0"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_ids() {
+        assert_eq!(preceding("1600000000000"), None);
+        assert_eq!(preceding("-"), None);
+        assert_eq!(preceding("a-b"), None);
+    }
+
+    #[test]
+    fn bounds_searches_by_time() {
+        assert_eq!(earliest(Some(1_600_000_000)), "1600000000000-0");
+        assert_eq!(earliest(None), "-");
+        assert_eq!(earliest(Some(0)), "-");
+        assert_eq!(earliest(Some(-5)), "-");
+        assert_eq!(earliest(Some(i64::MAX)), format!("{}-0", i64::MAX));
+    }
+}
@@ -17,14 +17,61 @@
 //
 
 use log::debug;
-use r2d2_redis::redis::Commands;
 use serenity::{
     framework::standard::{macros::check, Args, CheckResult, CommandOptions},
     model::channel::Message,
     prelude::*,
 };
 
-use crate::get_db_handle;
+use crate::{
+    get_db_handle,
+    roles::{self, Role},
+};
+
+/// verifies that the executing user has at least the given role, letting them know if they don't
+fn require_role(context: &mut Context, message: &Message, minimum: Role) -> CheckResult {
+    debug!("checking if a user is at least a {}", minimum);
+
+    let role = {
+        let mut database = get_db_handle!(context.data.read());
+        match roles::of(&mut *database, message.author.id.0) {
+            Ok(role) => role,
+            Err(msg) => return CheckResult::new_log(msg),
+        }
+    };
+    if role >= Some(minimum) {
+        return CheckResult::Success;
+    }
+
+    // let the caller know that they lack the role
+    if let Err(msg) = message.channel_id.say(
+        &context,
+        match minimum {
+            Role::Owner => String::from("**Error:** You are not the owner of the bot!"),
+            Role::Admin => String::from("**Error:** You are not an admin!"),
+            _ => format!(
+                "**Error:** You need to be at least a {} to do that!",
+                minimum
+            ),
+        },
+    ) {
+        return CheckResult::new_log(msg);
+    }
+
+    CheckResult::new_log(format!("user was not at least a {}", minimum))
+}
+
+// check that verifies if the executing user is the owner of the bot
+#[check]
+#[name = "Owner"]
+pub fn bot_owner(
+    context: &mut Context,
+    message: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> CheckResult {
+    require_role(context, message, Role::Owner)
+}
 
 // check that verifies if the executing user is a bot admin
 #[check]
@@ -35,28 +82,29 @@ pub fn bot_administrator(
     _: &mut Args,
     _: &CommandOptions,
 ) -> CheckResult {
-    debug!("checking if a user is an admin");
-
-    // get a database connection first
-    let mut database = get_db_handle!(context.data.read());
-
-    // verify
-    match database.sismember::<&str, u64, bool>("admins", message.author.id.0) {
-        Ok(is_admin) => {
-            if is_admin {
-                return CheckResult::Success;
-            }
-
-            // let the caller know that they are not an admin
-            if let Err(msg) = message
-                .channel_id
-                .say(&context, "**Error:** You are not an admin!")
-            {
-                return CheckResult::new_log(msg);
-            }
-
-            return CheckResult::new_log("user was not an admin");
-        }
-        Err(msg) => CheckResult::new_log(msg),
-    }
+    require_role(context, message, Role::Admin)
+}
+
+// check that verifies if the executing user is at least a moderator
+#[check]
+#[name = "Moderator"]
+pub fn bot_moderator(
+    context: &mut Context,
+    message: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> CheckResult {
+    require_role(context, message, Role::Moderator)
+}
+
+// check that verifies if the executing user is at least trusted
+#[check]
+#[name = "Trusted"]
+pub fn trusted_user(
+    context: &mut Context,
+    message: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> CheckResult {
+    require_role(context, message, Role::Trusted)
 }
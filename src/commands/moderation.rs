@@ -16,6 +16,7 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use chrono::Utc;
 use log::{debug, error};
 use r2d2_redis::redis::Commands;
 use serenity::{
@@ -23,67 +24,114 @@ use serenity::{
         macros::{command, group},
         Args, CommandResult,
     },
-    model::channel::Message,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
     prelude::*,
 };
+use std::collections::BTreeSet;
 
-use crate::{commands::checks::ADMIN_CHECK, get_db_handle, say_error, utils::resolve_user};
+use crate::{
+    appeals, audit, colors,
+    commands::checks::{ADMIN_CHECK, MODERATOR_CHECK},
+    get_db_handle, mirror, modlog, network, reports,
+    roles::{self, Role},
+    say_error, strikes, types,
+    utils::{
+        add_duration, format_timestamp, parse_duration, resolve_single_user, resolve_user,
+        sub_duration,
+    },
+};
 
 #[group]
 #[description = "Commands used to moderate the mirror channel"]
-#[commands(ban, unban)]
+#[commands(
+    ban, unban, banlist, baninfo, guildban, guildunban, guildbans, mute, unmute, warn, strikes,
+    pardon, purge, report, appeal, appeals
+)]
 pub struct Moderation;
 
 #[command]
-#[description = "Bans a user from the global mirror channel"]
+#[description = "Bans a user from this server's mirror network, optionally only for a while (e.g. `30m`, `2h`, `7d`)"]
+#[usage = "<user> [duration] [reason]"]
+#[min_args(1)]
 #[only_in(guilds)]
-#[checks(Admin)]
-pub fn ban(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
-    let user_id = match resolve_user(context, message, arguments) {
-        Ok(ids) => {
-            if ids.len() > 1 {
-                say_error!(
-                    message,
-                    context,
-                    "Usercache listing is not implemented yet!"
-                );
-                return Ok(());
-            } else if ids.len() == 0 {
-                say_error!(message, context, "No user could be found!");
-                return Ok(());
-            }
-            ids[0]
+#[checks(Moderator)]
+pub fn ban(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
         }
+    };
+
+    // the duration is optional, so anything that isn't one is the start of the reason
+    let duration = arguments.current().and_then(parse_duration);
+    if duration.is_some() {
+        arguments.advance();
+    }
+    let reason = arguments.rest().trim();
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
         Err(msg) => {
             say_error!(message, context, msg);
             return Ok(());
         }
     };
 
-    debug!("banning user id {} from the global mirror channel", user_id);
+    debug!("banning user id {} from network {}", user_id, network);
 
     let mut database = get_db_handle!(context.data.read());
 
-    // check if the user we're banning is an admin
-    match database.sismember::<&str, u64, bool>("admins", user_id) {
-        Ok(admin) => {
-            if admin {
-                say_error!(message, context, "You cannot ban an admin!");
+    // staff can't be punished, only stripped of their role first
+    match roles::of(&mut *database, user_id) {
+        Ok(role) => {
+            if role >= Some(Role::Moderator) {
+                say_error!(message, context, "You cannot ban a member of staff!");
                 return Ok(());
             }
         }
         Err(msg) => {
             error!(
-                "unable to check if the provided user id is of an admin: {:?}",
+                "unable to check the role of the provided user id: {:?}",
                 msg
             );
-            say_error!(message, context, "Unable to check if the user is an admin!");
+            say_error!(
+                message,
+                context,
+                "Unable to check if the user is a member of staff!"
+            );
             return Ok(());
         }
     }
 
     // ban them
-    match database.sadd::<&str, u64, bool>("banned", user_id) {
+    let now = Utc::now().timestamp();
+    let until = match duration {
+        Some(duration) => match add_duration(now, duration) {
+            Some(until) => Some(until),
+            None => {
+                say_error!(message, context, "That duration is too long!");
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let record = network::BanRecord {
+        moderator_id: message.author.id.0,
+        guild_id: message.guild_id.unwrap().0,
+        reason: if reason.is_empty() {
+            None
+        } else {
+            Some(String::from(reason))
+        },
+        banned_at: now,
+        expires_at: until,
+    };
+    match network::ban(&mut *database, &network, user_id, &record) {
         Ok(_) => (),
         Err(msg) => {
             error!(
@@ -99,12 +147,33 @@ pub fn ban(context: &mut Context, message: &Message, arguments: Args) -> Command
         }
     }
 
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Ban,
+            target: Some(audit::Target::User(user_id)),
+            network: Some(&network),
+            reason: record.reason.as_deref(),
+        },
+    );
+
     // provide a form of feedback
     message.channel_id.say(
         &context,
         format!(
-            "Successfully banned `{}` from the global mirror channel!",
-            user_id
+            "Successfully banned `{}` from the `{}` network{}{}!",
+            user_id,
+            network,
+            match until {
+                Some(until) => format!(" until {}", format_timestamp(until)),
+                None => String::new(),
+            },
+            if reason.is_empty() {
+                String::new()
+            } else {
+                format!(" for: {}", reason)
+            }
         ),
     )?;
 
@@ -112,9 +181,9 @@ pub fn ban(context: &mut Context, message: &Message, arguments: Args) -> Command
 }
 
 #[command]
-#[description = "Unbans a user from the global mirror channel"]
+#[description = "Unbans a user from this server's mirror network"]
 #[only_in(guilds)]
-#[checks(Admin)]
+#[checks(Moderator)]
 pub fn unban(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
     let user_id = match resolve_user(context, message, arguments) {
         Ok(ids) => {
@@ -137,15 +206,20 @@ pub fn unban(context: &mut Context, message: &Message, arguments: Args) -> Comma
         }
     };
 
-    debug!(
-        "unbanning user id {} from the global mirror channel",
-        user_id
-    );
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    debug!("unbanning user id {} from network {}", user_id, network);
 
     let mut database = get_db_handle!(context.data.read());
 
     // unban them
-    match database.srem::<&str, u64, bool>("banned", user_id) {
+    match network::unban(&mut *database, &network, user_id) {
         Ok(_) => (),
         Err(msg) => {
             error!(
@@ -161,14 +235,1747 @@ pub fn unban(context: &mut Context, message: &Message, arguments: Args) -> Comma
         }
     }
 
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Unban,
+            target: Some(audit::Target::User(user_id)),
+            network: Some(&network),
+            reason: None,
+        },
+    );
+
     // provide a form of feedback
     message.channel_id.say(
         &context,
         format!(
-            "Successfully unbanned `{}` from the global mirror channel!",
-            user_id
+            "Successfully unbanned `{}` from the `{}` network!",
+            user_id, network
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// how many bans are shown per page
+const BANS_PER_PAGE: usize = 10;
+
+/// describes a ban record for humans
+fn describe_ban(record: &Option<network::BanRecord>) -> String {
+    match record {
+        Some(record) => format!(
+            "**Moderator:** <@{}>\n**Issued from:** `{}`\n**Banned:** {}\n**Expires:** {}\n**Reason:** {}",
+            record.moderator_id,
+            record.guild_id,
+            format_timestamp(record.banned_at),
+            match record.expires_at {
+                Some(expires_at) => format_timestamp(expires_at),
+                None => String::from("Never"),
+            },
+            record.reason.as_deref().unwrap_or("No reason was given")
+        ),
+        None => String::from("This ban was issued before bans were recorded"),
+    }
+}
+
+#[command]
+#[description = "Lists the users banned from this server's mirror network"]
+#[usage = "[page]"]
+#[max_args(1)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn banlist(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let page = if arguments.is_empty() {
+        1
+    } else {
+        match arguments.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided page number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let bans = match network::bans(&mut *database, &network) {
+        Ok(bans) => bans,
+        Err(msg) => {
+            error!("unable to retrieve the bans of a network: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the banned users!");
+            return Ok(());
+        }
+    };
+
+    let count = bans.len();
+    let fields = bans
+        .iter()
+        .skip((page - 1) * BANS_PER_PAGE)
+        .take(BANS_PER_PAGE)
+        .map(|(user_id, record)| (format!("{}", user_id), describe_ban(record), false))
+        .collect::<Vec<_>>();
+
+    let pages = count.div_ceil(BANS_PER_PAGE).max(1);
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title(format!("Bans in the `{}` network", network))
+                .description(if fields.is_empty() {
+                    "There are no bans on this page"
+                } else {
+                    "Lift one with `unban <user>`"
+                })
+                .fields(fields)
+                .footer(|f| f.text(format!("Page {} of {} ({} total)", page, pages, count)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows why and by whom a user was banned from this server's mirror network"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn baninfo(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let record = match network::is_banned(&mut *database, &network, user_id) {
+        Ok(true) => match network::ban_record(&mut *database, &network, user_id) {
+            Ok(record) => record,
+            Err(msg) => {
+                error!("unable to retrieve a ban record: {:?}", msg);
+                say_error!(message, context, "Unable to retrieve the ban!");
+                return Ok(());
+            }
+        },
+        Ok(false) => {
+            say_error!(
+                message,
+                context,
+                format!(
+                    "`{}` is not banned from the `{}` network!",
+                    user_id, network
+                )
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to check if a user is banned: {:?}", msg);
+            say_error!(message, context, "Unable to check if the user is banned!");
+            return Ok(());
+        }
+    };
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title(format!(
+                "Ban of `{}` from the `{}` network",
+                user_id, network
+            ))
+            .description(describe_ban(&record))
+            .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Bans a server from this server's mirror network, removing its mirror channel from the network"]
+#[usage = "<server id> [reason]"]
+#[min_args(1)]
+#[only_in(guilds)]
+#[checks(Admin)]
+pub fn guildban(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let guild_id = match arguments.single::<u64>() {
+        Ok(guild_id) => guild_id,
+        Err(_) => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a server id!"
+            );
+            return Ok(());
+        }
+    };
+    let reason = arguments.rest().trim();
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    if Some(GuildId(guild_id)) == message.guild_id {
+        say_error!(message, context, "You cannot ban this server!");
+        return Ok(());
+    }
+
+    debug!("banning guild id {} from network {}", guild_id, network);
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let record = network::BanRecord {
+        moderator_id: message.author.id.0,
+        guild_id: message.guild_id.unwrap().0,
+        reason: if reason.is_empty() {
+            None
+        } else {
+            Some(String::from(reason))
+        },
+        banned_at: Utc::now().timestamp(),
+        expires_at: None,
+    };
+    match network::ban_guild(&mut *database, &network, guild_id, &record) {
+        Ok(_) => (),
+        Err(msg) => {
+            error!("unable to ban a guild from a network: {:?}", msg);
+            say_error!(
+                message,
+                context,
+                "Unable to add the server to the banned servers' list!"
+            );
+            return Ok(());
+        }
+    }
+
+    // kick the server's mirror channel out of the network if it is in it
+    match network::of_guild(&mut *database, guild_id) {
+        Ok(Some((channel_id, guild_network))) if guild_network == network => {
+            match network::unlink(&mut *database, guild_id) {
+                Ok(_) => network::notify_unlinked(
+                    context,
+                    guild_id,
+                    channel_id,
+                    &network,
+                    "the server was banned from it",
+                ),
+                Err(msg) => {
+                    error!(
+                        "unable to unlink the mirror channel of a banned guild: {:?}",
+                        msg
+                    );
+                    say_error!(
+                        message,
+                        context,
+                        "The server was banned, but its mirror channel could not be removed!"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        Ok(_) => (),
+        Err(msg) => {
+            error!(
+                "unable to check for an existing mirror channel in a guild: {:?}",
+                msg
+            );
+            say_error!(
+                message,
+                context,
+                "The server was banned, but its mirror channel could not be looked up!"
+            );
+            return Ok(());
+        }
+    }
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::GuildBan,
+            target: Some(audit::Target::Guild(guild_id)),
+            network: Some(&network),
+            reason: record.reason.as_deref(),
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Successfully banned the server `{}` from the `{}` network{}!",
+            guild_id,
+            network,
+            if reason.is_empty() {
+                String::new()
+            } else {
+                format!(" for: {}", reason)
+            }
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Unbans a server from this server's mirror network"]
+#[usage = "<server id>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[checks(Admin)]
+pub fn guildunban(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let guild_id = match arguments.parse::<u64>() {
+        Ok(guild_id) => guild_id,
+        Err(_) => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a server id!"
+            );
+            return Ok(());
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    debug!("unbanning guild id {} from network {}", guild_id, network);
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::unban_guild(&mut *database, &network, guild_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(
+                message,
+                context,
+                format!(
+                    "The server `{}` is not banned from the `{}` network!",
+                    guild_id, network
+                )
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to unban a guild from a network: {:?}", msg);
+            say_error!(
+                message,
+                context,
+                "Unable to remove the server from the banned servers' list!"
+            );
+            return Ok(());
+        }
+    }
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::GuildUnban,
+            target: Some(audit::Target::Guild(guild_id)),
+            network: Some(&network),
+            reason: None,
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Successfully unbanned the server `{}` from the `{}` network! It will have to enable its mirror channel again",
+            guild_id, network
         ),
     )?;
 
     Ok(())
 }
+
+#[command]
+#[description = "Lists the servers banned from this server's mirror network"]
+#[usage = "[page]"]
+#[max_args(1)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn guildbans(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let page = if arguments.is_empty() {
+        1
+    } else {
+        match arguments.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided page number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let bans = match network::guild_bans(&mut *database, &network) {
+        Ok(bans) => bans,
+        Err(msg) => {
+            error!("unable to retrieve the guild bans of a network: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the banned servers!");
+            return Ok(());
+        }
+    };
+
+    let count = bans.len();
+    let fields = bans
+        .iter()
+        .skip((page - 1) * BANS_PER_PAGE)
+        .take(BANS_PER_PAGE)
+        .map(|(guild_id, record)| (format!("{}", guild_id), describe_ban(record), false))
+        .collect::<Vec<_>>();
+
+    let pages = count.div_ceil(BANS_PER_PAGE).max(1);
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title(format!("Banned servers in the `{}` network", network))
+                .description(if fields.is_empty() {
+                    "There are no banned servers on this page"
+                } else {
+                    "Lift one with `guildunban <server id>`"
+                })
+                .fields(fields)
+                .footer(|f| f.text(format!("Page {} of {} ({} total)", page, pages, count)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Stops a user's messages from being mirrored in this server's mirror network for a while (e.g. `30m`, `2h`, `7d`). Unlike a ban, they are reminded that they are muted when they talk"]
+#[usage = "<user> <duration> [reason]"]
+#[min_args(2)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn mute(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let duration = match arguments
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_duration)
+    {
+        Some(duration) => duration,
+        None => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a duration!"
+            );
+            return Ok(());
+        }
+    };
+    let reason = arguments.rest().trim();
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    debug!("muting user id {} in network {}", user_id, network);
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match roles::of(&mut *database, user_id) {
+        Ok(role) => {
+            if role >= Some(Role::Moderator) {
+                say_error!(message, context, "You cannot mute a member of staff!");
+                return Ok(());
+            }
+        }
+        Err(msg) => {
+            error!(
+                "unable to check the role of the provided user id: {:?}",
+                msg
+            );
+            say_error!(
+                message,
+                context,
+                "Unable to check if the user is a member of staff!"
+            );
+            return Ok(());
+        }
+    }
+
+    let until = match add_duration(Utc::now().timestamp(), duration) {
+        Some(until) => until,
+        None => {
+            say_error!(message, context, "That duration is too long!");
+            return Ok(());
+        }
+    };
+    match network::mute(&mut *database, &network, user_id, until) {
+        Ok(_) => (),
+        Err(msg) => {
+            error!("unable to mute a user: {:?}", msg);
+            say_error!(message, context, "Unable to mute the user!");
+            return Ok(());
+        }
+    }
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Mute,
+            target: Some(audit::Target::User(user_id)),
+            network: Some(&network),
+            reason: if reason.is_empty() {
+                None
+            } else {
+                Some(reason)
+            },
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Successfully muted `{}` in the `{}` network until {}{}!",
+            user_id,
+            network,
+            format_timestamp(until),
+            if reason.is_empty() {
+                String::new()
+            } else {
+                format!(" for: {}", reason)
+            }
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lets a muted user's messages be mirrored in this server's mirror network again"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn unmute(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    debug!("unmuting user id {} in network {}", user_id, network);
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::unmute(&mut *database, &network, user_id) {
+        Ok(true) => {
+            audit::record(
+                context,
+                &message.into(),
+                &audit::Entry {
+                    action: audit::Action::Unmute,
+                    target: Some(audit::Target::User(user_id)),
+                    network: Some(&network),
+                    reason: None,
+                },
+            );
+
+            message.channel_id.say(
+                &context,
+                format!(
+                    "Successfully unmuted `{}` in the `{}` network!",
+                    user_id, network
+                ),
+            )?;
+        }
+        Ok(false) => {
+            say_error!(
+                message,
+                context,
+                format!("`{}` is not muted in the `{}` network!", user_id, network)
+            );
+        }
+        Err(msg) => {
+            error!("unable to unmute a user: {:?}", msg);
+            say_error!(message, context, "Unable to unmute the user!");
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Gives a user a strike in this server's mirror network and lets them know. Enough strikes get them muted or banned automatically"]
+#[usage = "<user> <reason>"]
+#[min_args(2)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn warn(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+    let reason = arguments.rest().trim();
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    debug!("warning user id {} in network {}", user_id, network);
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match roles::of(&mut *database, user_id) {
+        Ok(role) => {
+            if role >= Some(Role::Moderator) {
+                say_error!(message, context, "You cannot warn a member of staff!");
+                return Ok(());
+            }
+        }
+        Err(msg) => {
+            error!(
+                "unable to check the role of the provided user id: {:?}",
+                msg
+            );
+            say_error!(
+                message,
+                context,
+                "Unable to check if the user is a member of staff!"
+            );
+            return Ok(());
+        }
+    }
+
+    let now = Utc::now().timestamp();
+    let strike = strikes::Strike {
+        moderator_id: message.author.id.0,
+        guild_id: message.guild_id.unwrap().0,
+        reason: String::from(reason),
+        issued_at: now,
+    };
+    let count = match strikes::add(&mut *database, &network, user_id, &strike) {
+        Ok(count) => count,
+        Err(msg) => {
+            error!("unable to record a strike: {:?}", msg);
+            say_error!(message, context, "Unable to record the strike!");
+            return Ok(());
+        }
+    };
+
+    // escalate to the harshest punishment the user has earned
+    let escalation = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => strikes::escalation(&cfg.strikes, count, now),
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+    let punishment = match escalation {
+        strikes::Escalation::Ban { until } => {
+            let record = network::BanRecord {
+                moderator_id: message.author.id.0,
+                guild_id: message.guild_id.unwrap().0,
+                reason: Some(format!("Reached {} strikes. Latest: {}", count, reason)),
+                banned_at: now,
+                expires_at: until,
+            };
+            match network::ban(&mut *database, &network, user_id, &record) {
+                Ok(_) => Some((
+                    audit::Action::Ban,
+                    match until {
+                        Some(until) => format!("banned until {}", format_timestamp(until)),
+                        None => String::from("banned"),
+                    },
+                )),
+                Err(msg) => {
+                    error!(
+                        "unable to ban a user for reaching too many strikes: {:?}",
+                        msg
+                    );
+                    say_error!(
+                        message,
+                        context,
+                        "The strike was recorded, but the user could not be banned!"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        strikes::Escalation::Mute { until } => {
+            match network::mute(&mut *database, &network, user_id, until) {
+                Ok(_) => Some((
+                    audit::Action::Mute,
+                    format!("muted until {}", format_timestamp(until)),
+                )),
+                Err(msg) => {
+                    error!(
+                        "unable to mute a user for reaching too many strikes: {:?}",
+                        msg
+                    );
+                    say_error!(
+                        message,
+                        context,
+                        "The strike was recorded, but the user could not be muted!"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        strikes::Escalation::None => None,
+    };
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Warn,
+            target: Some(audit::Target::User(user_id)),
+            network: Some(&network),
+            reason: Some(reason),
+        },
+    );
+
+    if let Some((action, _)) = &punishment {
+        audit::record(
+            context,
+            &message.into(),
+            &audit::Entry {
+                action: *action,
+                target: Some(audit::Target::User(user_id)),
+                network: Some(&network),
+                reason: Some(&format!("Reached {} strikes", count)),
+            },
+        );
+    }
+
+    let notified = UserId(user_id)
+        .create_dm_channel(&context)
+        .and_then(|channel| {
+            channel.say(
+                &context,
+                format!(
+                    "You have received a strike in the `{}` mirror network for: {}\nYou now have {} strike(s){}",
+                    network,
+                    reason,
+                    count,
+                    match &punishment {
+                        Some((_, punishment)) => format!(" and have been {}.", punishment),
+                        None => String::from("."),
+                    }
+                ),
+            )
+        });
+    if let Err(msg) = &notified {
+        debug!("unable to notify user {} of a strike: {:?}", user_id, msg);
+    }
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Gave `{}` a strike in the `{}` network. They now have {} strike(s){}{}",
+            user_id,
+            network,
+            count,
+            match &punishment {
+                Some((_, punishment)) => format!(" and have been {}", punishment),
+                None => String::new(),
+            },
+            if notified.is_err() {
+                ". They could not be messaged about it!"
+            } else {
+                "!"
+            }
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists a user's strikes in this server's mirror network"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn strikes(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let list = match strikes::list(&mut *database, &network, user_id) {
+        Ok(list) => list,
+        Err(msg) => {
+            error!("unable to retrieve the strikes of a user: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the user's strikes!");
+            return Ok(());
+        }
+    };
+
+    // discord only allows so many fields in an embed, so only show the latest strikes
+    let fields = list
+        .iter()
+        .rev()
+        .take(25)
+        .map(|(index, strike)| {
+            (
+                format!("#{}", index + 1),
+                format!(
+                    "**Moderator:** <@{}>\n**Issued from:** `{}`\n**Issued:** {}\n**Reason:** {}",
+                    strike.moderator_id,
+                    strike.guild_id,
+                    format_timestamp(strike.issued_at),
+                    strike.reason
+                ),
+                false,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title(format!(
+                "Strikes of `{}` in the `{}` network",
+                user_id, network
+            ))
+            .description(if fields.is_empty() {
+                String::from("This user has no strikes")
+            } else {
+                format!(
+                    "{} strike(s). Remove one with `pardon <user> <number>`, or all of them with `pardon <user>`",
+                    list.len()
+                )
+            })
+            .fields(fields)
+            .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Removes one or all of a user's strikes in this server's mirror network. Punishments that were already given out are left alone"]
+#[usage = "<user> [number]"]
+#[min_args(1)]
+#[max_args(2)]
+#[only_in(guilds)]
+#[checks(Moderator)]
+pub fn pardon(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let number = if arguments.is_empty() {
+        None
+    } else {
+        match arguments.single::<usize>() {
+            Ok(number) if number > 0 => Some(number),
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided strike number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let network = match network::resolve(context, message) {
+        Ok(network) => network,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let result = match number {
+        Some(number) => strikes::pardon(&mut *database, &network, user_id, number - 1)
+            .map(|removed| if removed { 1 } else { 0 }),
+        None => strikes::pardon_all(&mut *database, &network, user_id),
+    };
+    match result {
+        Ok(0) => {
+            say_error!(message, context, "There are no strikes to remove!");
+        }
+        Ok(count) => {
+            audit::record(
+                context,
+                &message.into(),
+                &audit::Entry {
+                    action: audit::Action::Pardon,
+                    target: Some(audit::Target::User(user_id)),
+                    network: Some(&network),
+                    reason: None,
+                },
+            );
+
+            message.channel_id.say(
+                &context,
+                format!(
+                    "Removed {} strike(s) from `{}` in the `{}` network!",
+                    count, user_id, network
+                ),
+            )?;
+        }
+        Err(msg) => {
+            error!("unable to pardon a user: {:?}", msg);
+            say_error!(message, context, "Unable to remove the strikes!");
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Deletes a user's recent messages from every mirror channel. Either how many messages or how far back to go (e.g. `30m`, `2h`, `1d`) may be given, otherwise every message the bot remembers is deleted"]
+#[usage = "<user> [count|duration]"]
+#[min_args(1)]
+#[max_args(2)]
+#[checks(Moderator)]
+pub fn purge(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let (count, since) = match arguments.single::<String>() {
+        Ok(limit) => match limit.parse::<isize>() {
+            Ok(count) if count > 0 => (Some(count), None),
+            _ => match parse_duration(&limit) {
+                // durations are bounded, so this only falls back to the epoch if the clock is
+                // wildly off
+                Some(duration) => (
+                    None,
+                    Some(sub_duration(Utc::now().timestamp(), duration).unwrap_or(0)),
+                ),
+                None => {
+                    say_error!(
+                        message,
+                        context,
+                        "Unable to parse the provided argument to a message count or a duration!"
+                    );
+                    return Ok(());
+                }
+            },
+        },
+        Err(_) => (None, None),
+    };
+
+    debug!("purging the mirrored messages of user id {}", user_id);
+
+    // send the initial status message
+    let mut status_message = message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Purging")
+                .description(format!("Purging the messages of `{}`", user_id))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    // the deletions are requests to discord, so a connection is only held around the queries
+    let mut database = get_db_handle!(context.data.read());
+
+    let message_ids = match mirror::by_author(&mut *database, user_id, count, since) {
+        Ok(message_ids) => message_ids,
+        Err(msg) => {
+            error!(
+                "unable to retrieve the mirrored messages of a user: {:?}",
+                msg
+            );
+            say_error!(
+                message,
+                context,
+                "Unable to retrieve the user's mirrored messages!"
+            );
+            return Ok(());
+        }
+    };
+
+    drop(database);
+
+    let mut removed = 0;
+    let mut failed = BTreeSet::new();
+    for message_id in &message_ids {
+        let mut database = get_db_handle!(context.data.read());
+        let origin = match mirror::origin(&mut *database, *message_id) {
+            Ok(origin) => origin,
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the origin of a mirrored message: {:?}",
+                    msg
+                );
+                None
+            }
+        };
+        let replicas = match mirror::replicas(&mut *database, *message_id) {
+            Ok(replicas) => replicas,
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the copies of a mirrored message: {:?}",
+                    msg
+                );
+                continue;
+            }
+        };
+        drop(database);
+
+        for replica in replicas {
+            match mirror::delete(context, &replica) {
+                Ok(_) => removed += 1,
+                Err(msg) => {
+                    error!(
+                        "unable to delete the copy of a mirrored message in channel {}: {:?}",
+                        replica.channel_id, msg
+                    );
+                    failed.insert(replica.channel_id);
+                }
+            }
+        }
+
+        // forget about the message before deleting the original so that the deletion isn't
+        // propagated a second time
+        let mut database = get_db_handle!(context.data.read());
+        if let Err(msg) = mirror::forget(&mut *database, *message_id) {
+            error!("unable to forget about a purged message: {:?}", msg);
+        }
+        drop(database);
+
+        if let Some(origin) = origin {
+            match ChannelId(origin.channel_id).delete_message(&context, *message_id) {
+                Ok(_) => removed += 1,
+                Err(msg) => {
+                    error!(
+                        "unable to delete an original message in channel {}: {:?}",
+                        origin.channel_id, msg
+                    );
+                    failed.insert(origin.channel_id);
+                }
+            }
+        }
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+    if let Err(msg) = mirror::unindex(&mut *database, user_id, &message_ids) {
+        error!(
+            "unable to remove purged messages from a user's index: {:?}",
+            msg
+        );
+    }
+    drop(database);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Purge,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    // update the status message one last time
+    let mut description = format!(
+        "Removed {} messages sent by `{}` across every mirror channel.",
+        removed, user_id
+    );
+    if !failed.is_empty() {
+        description.push_str("\n\n**Unable to remove messages from:** ");
+        description.push_str(
+            &failed
+                .iter()
+                .map(|channel| format!("<#{}>", channel))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+    status_message.edit(&context, |m| {
+        m.embed(|e| {
+            e.title("Purging")
+                .description(description)
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+/// posts a report where the moderators of its network will see it, returning how many places it
+/// was posted to
+fn post_report(context: &Context, report: &reports::Report) -> usize {
+    let mut database = get_db_handle!(context.data.read());
+
+    let mut posted = 0;
+    for channel in modlog::destinations(context, &report.network) {
+        let post = match channel.send_message(context, |m| {
+            m.embed(|e| {
+                e.title(format!("Report #{}", report.id))
+                    .field(
+                        "Author",
+                        format!("<@{}> (`{}`)", report.author_id, report.author_id),
+                        true,
+                    )
+                    .field(
+                        "Reporter",
+                        format!("<@{}> (`{}`)", report.reporter_id, report.reporter_id),
+                        true,
+                    )
+                    .field("Network", &report.network, true)
+                    .field("Reported", format_timestamp(report.created_at), true)
+                    .field("Reason", &report.reason, false)
+                    .field(
+                        "Message",
+                        format!(
+                            "{}\n[Jump to the original](https://discordapp.com/channels/{}/{}/{})",
+                            report
+                                .content
+                                .as_deref()
+                                .filter(|content| !content.is_empty())
+                                .unwrap_or("*The message could not be retrieved*"),
+                            report.guild_id,
+                            report.channel_id,
+                            report.message_id
+                        ),
+                        false,
+                    )
+                    .footer(|f| {
+                        f.text(format!(
+                            "React with {} to ban the author or {} to dismiss the report",
+                            reports::BAN_EMOJI,
+                            reports::DISMISS_EMOJI
+                        ))
+                    })
+                    .color(colors::WARNING)
+            })
+        }) {
+            Ok(post) => post,
+            Err(msg) => {
+                error!(
+                    "unable to post report #{} in channel {}: {:?}",
+                    report.id, channel.0, msg
+                );
+                continue;
+            }
+        };
+        posted += 1;
+
+        if let Err(msg) = reports::link_post(&mut *database, post.id.0, report.id) {
+            error!("unable to remember where a report was posted: {:?}", msg);
+        }
+        for emoji in &[reports::BAN_EMOJI, reports::DISMISS_EMOJI] {
+            if let Err(msg) = post.react(context, *emoji) {
+                error!("unable to add a quick action to a report: {:?}", msg);
+            }
+        }
+    }
+
+    posted
+}
+
+#[command]
+#[description = "Reports a mirrored message to the moderators of its network"]
+#[usage = "<message link|id> <reason>"]
+#[min_args(2)]
+#[only_in(guilds)]
+pub fn report(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    // message links end with the id of the message
+    let reported_id = match arguments
+        .single::<String>()?
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .and_then(|id| id.parse::<u64>().ok())
+    {
+        Some(id) => id,
+        None => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a message link or id!"
+            );
+            return Ok(());
+        }
+    };
+    let reason = arguments.rest().trim();
+
+    let mut database = get_db_handle!(context.data.read());
+
+    // copies are traced back to the message they were made from
+    let message_id = match mirror::original_of(&mut *database, reported_id) {
+        Ok(original) => original.unwrap_or(reported_id),
+        Err(msg) => {
+            error!("unable to look up the original of a message: {:?}", msg);
+            say_error!(message, context, "Unable to look up that message!");
+            return Ok(());
+        }
+    };
+    let origin = match mirror::origin(&mut *database, message_id) {
+        Ok(Some(origin)) => origin,
+        Ok(None) => {
+            say_error!(
+                message,
+                context,
+                "That message was not mirrored, or is too old to be reported!"
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to retrieve the origin of a message: {:?}", msg);
+            say_error!(message, context, "Unable to look up that message!");
+            return Ok(());
+        }
+    };
+
+    let content = match ChannelId(origin.channel_id).message(&context, message_id) {
+        Ok(original) => Some(original.content),
+        Err(msg) => {
+            debug!("unable to retrieve a reported message: {:?}", msg);
+            None
+        }
+    };
+
+    let mut report = reports::Report {
+        id: 0,
+        reporter_id: message.author.id.0,
+        reporter_guild_id: message.guild_id.unwrap().0,
+        message_id,
+        channel_id: origin.channel_id,
+        guild_id: origin.guild_id,
+        author_id: origin.author_id,
+        network: origin.network,
+        content,
+        reason: String::from(reason),
+        created_at: Utc::now().timestamp(),
+        status: reports::Status::Open,
+    };
+    if let Err(msg) = reports::create(&mut *database, &mut report) {
+        error!("unable to save a report: {:?}", msg);
+        say_error!(message, context, "Unable to save your report!");
+        return Ok(());
+    }
+
+    debug!(
+        "user {} reported message {} by user {}",
+        report.reporter_id, report.message_id, report.author_id
+    );
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Report,
+            target: Some(audit::Target::User(report.author_id)),
+            network: Some(&report.network),
+            reason: Some(reason),
+        },
+    );
+
+    if post_report(context, &report) == 0 {
+        say_error!(
+            message,
+            context,
+            "Your report was saved, but no moderators could be notified of it!"
+        );
+        return Ok(());
+    }
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Thank you! Your report (#{}) has been sent to the moderators",
+            report.id
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// posts an appeal where the moderators of its network will see it, returning how many places
+/// it was posted to
+fn post_appeal(
+    context: &Context,
+    appeal: &appeals::Appeal,
+    ban: &Option<network::BanRecord>,
+) -> usize {
+    let prefix = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => cfg.prefix.clone(),
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+
+    let mut posted = 0;
+    for channel in modlog::destinations(context, &appeal.network) {
+        if let Err(msg) = channel.send_message(context, |m| {
+            m.embed(|e| {
+                e.title(format!("Appeal #{}", appeal.id))
+                    .field(
+                        "User",
+                        format!("<@{}> (`{}`)", appeal.user_id, appeal.user_id),
+                        true,
+                    )
+                    .field("Network", &appeal.network, true)
+                    .field("Appealed", format_timestamp(appeal.created_at), true)
+                    .field("Ban", describe_ban(ban), false)
+                    .field("Appeal", &appeal.message, false)
+                    .footer(|f| {
+                        f.text(format!(
+                            "Respond with {}appeals accept|deny {} [note]",
+                            prefix, appeal.id
+                        ))
+                    })
+                    .color(colors::WARNING)
+            })
+        }) {
+            error!(
+                "unable to post appeal #{} in channel {}: {:?}",
+                appeal.id, channel.0, msg
+            );
+            continue;
+        }
+        posted += 1;
+    }
+
+    posted
+}
+
+#[command]
+#[description = "Appeals your ban from a mirror network. The network only needs to be given if you are banned from several"]
+#[usage = "[network] <message>"]
+#[min_args(1)]
+#[only_in(dms)]
+pub fn appeal(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let mut database = get_db_handle!(context.data.read());
+
+    let networks = match database.smembers::<&str, Vec<String>>("networks") {
+        Ok(networks) => networks,
+        Err(msg) => {
+            error!("unable to retrieve the mirror networks: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the mirror networks!");
+            return Ok(());
+        }
+    };
+    let mut banned_from = BTreeSet::new();
+    for network in networks {
+        match network::is_banned(&mut *database, &network, message.author.id.0) {
+            Ok(true) => {
+                banned_from.insert(network);
+            }
+            Ok(false) => (),
+            Err(msg) => {
+                error!(
+                    "unable to check if user {} is banned from network {}: {:?}",
+                    message.author.id.0, network, msg
+                );
+                say_error!(message, context, "Unable to look up your bans!");
+                return Ok(());
+            }
+        }
+    }
+
+    let network = match arguments
+        .current()
+        .filter(|argument| banned_from.contains(*argument))
+    {
+        Some(network) => {
+            let network = String::from(network);
+            arguments.advance();
+            network
+        }
+        None => match banned_from.len() {
+            0 => {
+                say_error!(message, context, "You are not banned from any network!");
+                return Ok(());
+            }
+            1 => banned_from.into_iter().next().unwrap(),
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    format!(
+                        "You are banned from several networks, so you have to say which one you are appealing: {}",
+                        banned_from
+                            .iter()
+                            .map(|network| format!("`{}`", network))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                );
+                return Ok(());
+            }
+        },
+    };
+
+    let text = arguments.rest().trim();
+    if text.is_empty() {
+        say_error!(
+            message,
+            context,
+            "You have to explain why you should be unbanned!"
+        );
+        return Ok(());
+    }
+
+    let cooldown = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => cfg.appeal_cooldown,
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+    match appeals::start_cooldown(&mut *database, &network, message.author.id.0, cooldown) {
+        Ok(None) => (),
+        Ok(Some(remaining)) => {
+            say_error!(
+                message,
+                context,
+                format!(
+                    "You have already appealed your ban recently, and can appeal again after {}",
+                    format_timestamp(Utc::now().timestamp() + remaining.max(0))
+                )
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to start the cooldown of an appeal: {:?}", msg);
+            say_error!(message, context, "Unable to save your appeal!");
+            return Ok(());
+        }
+    }
+
+    let ban = match network::ban_record(&mut *database, &network, message.author.id.0) {
+        Ok(ban) => ban,
+        Err(msg) => {
+            error!("unable to retrieve a ban record: {:?}", msg);
+            None
+        }
+    };
+
+    let mut appeal = appeals::Appeal {
+        id: 0,
+        user_id: message.author.id.0,
+        network,
+        message: String::from(text),
+        created_at: Utc::now().timestamp(),
+        status: appeals::Status::Pending,
+        moderator_id: None,
+        note: None,
+    };
+    if let Err(msg) = appeals::create(&mut *database, &mut appeal) {
+        error!("unable to save an appeal: {:?}", msg);
+
+        // the user shouldn't have to wait out the cooldown of an appeal that doesn't exist
+        if let Err(msg) = appeals::end_cooldown(&mut *database, &appeal.network, appeal.user_id) {
+            error!("unable to end the cooldown of an appeal: {:?}", msg);
+        }
+        say_error!(message, context, "Unable to save your appeal!");
+        return Ok(());
+    }
+
+    debug!(
+        "user {} appealed their ban from network {}",
+        appeal.user_id, appeal.network
+    );
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Appeal,
+            target: Some(audit::Target::User(appeal.user_id)),
+            network: Some(&appeal.network),
+            reason: Some(text),
+        },
+    );
+
+    if post_appeal(context, &appeal, &ban) == 0 {
+        say_error!(
+            message,
+            context,
+            "Your appeal was saved, but no moderators could be notified of it!"
+        );
+        return Ok(());
+    }
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Your appeal (#{}) has been sent to the moderators of the `{}` network. You will be messaged once they have decided on it",
+            appeal.id, appeal.network
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// accepts or denies an appeal, unbanning the user if it was accepted and letting them know
+fn decide_appeal(
+    context: &mut Context,
+    message: &Message,
+    mut arguments: Args,
+    accepted: bool,
+) -> CommandResult {
+    let id = match arguments.single::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to an appeal id!"
+            );
+            return Ok(());
+        }
+    };
+    let note = Some(arguments.rest().trim()).filter(|note| !note.is_empty());
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let mut appeal = match appeals::get(&mut *database, id) {
+        Ok(Some(appeal)) => appeal,
+        Ok(None) => {
+            say_error!(message, context, "No appeal with that id exists!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to retrieve an appeal: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve that appeal!");
+            return Ok(());
+        }
+    };
+    if appeal.status != appeals::Status::Pending {
+        say_error!(message, context, "That appeal has already been decided on!");
+        return Ok(());
+    }
+
+    if accepted {
+        if let Err(msg) = network::unban(&mut *database, &appeal.network, appeal.user_id) {
+            error!(
+                "unable to update the banned user set to remove a new banned user: {:?}",
+                msg
+            );
+            say_error!(
+                message,
+                context,
+                "Unable to remove the user from the banned users' list!"
+            );
+            return Ok(());
+        }
+    }
+
+    appeal.status = if accepted {
+        appeals::Status::Accepted
+    } else {
+        appeals::Status::Denied
+    };
+    appeal.moderator_id = Some(message.author.id.0);
+    appeal.note = note.map(String::from);
+    if let Err(msg) = appeals::save(&mut *database, &appeal) {
+        error!("unable to save an appeal: {:?}", msg);
+        say_error!(
+            message,
+            context,
+            "Unable to save the decision on that appeal!"
+        );
+        return Ok(());
+    }
+
+    debug!(
+        "appeal #{} by user {} was {}",
+        appeal.id,
+        appeal.user_id,
+        if accepted { "accepted" } else { "denied" }
+    );
+
+    let outcome = if accepted { "accepted" } else { "denied" };
+    let reason = match note {
+        Some(note) => format!("Appeal #{} {}: {}", appeal.id, outcome, note),
+        None => format!("Appeal #{} {}", appeal.id, outcome),
+    };
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: if accepted {
+                audit::Action::Unban
+            } else {
+                audit::Action::Deny
+            },
+            target: Some(audit::Target::User(appeal.user_id)),
+            network: Some(&appeal.network),
+            reason: Some(&reason),
+        },
+    );
+
+    let notified = UserId(appeal.user_id)
+        .create_dm_channel(&context)
+        .and_then(|channel| {
+            channel.say(
+                &context,
+                format!(
+                    "Your appeal of your ban from the `{}` mirror network has been {}{}",
+                    appeal.network,
+                    if accepted {
+                        "accepted, and you have been unbanned"
+                    } else {
+                        "denied"
+                    },
+                    match note {
+                        Some(note) => format!(". The moderators said: {}", note),
+                        None => String::from("."),
+                    }
+                ),
+            )
+        });
+    if let Err(msg) = &notified {
+        debug!(
+            "unable to notify user {} of the outcome of an appeal: {:?}",
+            appeal.user_id, msg
+        );
+    }
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "{} appeal #{} by `{}`{}",
+            if accepted {
+                "Accepted and unbanned the user of"
+            } else {
+                "Denied"
+            },
+            appeal.id,
+            appeal.user_id,
+            if notified.is_err() {
+                ". They could not be messaged about it!"
+            } else {
+                "!"
+            }
+        ),
+    )?;
+
+    Ok(())
+}
+
+// deciding on appeals is kept out of `appeal` itself, as appeals starting with the name of a
+// subcommand would be mistaken for it
+#[command]
+#[description = "Decides on appeals of network bans"]
+#[usage = "<accept|deny> <id> [note]"]
+#[checks(Moderator)]
+#[sub_commands(appeals_accept, appeals_deny)]
+pub fn appeals(context: &mut Context, message: &Message) -> CommandResult {
+    say_error!(
+        message,
+        context,
+        "Unknown subcommand! Use `accept` or `deny`"
+    );
+    Ok(())
+}
+
+#[command("accept")]
+#[description = "Accepts an appeal, unbanning the user who made it"]
+#[usage = "<id> [note]"]
+#[min_args(1)]
+#[checks(Moderator)]
+pub fn appeals_accept(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    decide_appeal(context, message, arguments, true)
+}
+
+#[command("deny")]
+#[description = "Denies an appeal"]
+#[usage = "<id> [note]"]
+#[min_args(1)]
+#[checks(Moderator)]
+pub fn appeals_deny(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    decide_appeal(context, message, arguments, false)
+}
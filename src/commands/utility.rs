@@ -16,28 +16,66 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use chrono::Utc;
 use log::{debug, error};
-use r2d2_redis::redis::{self, Commands};
+use r2d2_redis::redis::{self, Commands, PipelineCommands};
 use serenity::{
     framework::standard::{
         macros::{command, group},
         Args, CommandResult,
     },
     model::{
-        channel::{Channel, Message},
-        id::{ChannelId, UserId},
+        channel::{Channel, ChannelType, Message},
+        id::{ChannelId, GuildId, UserId},
+        permissions::Permissions,
     },
     prelude::*,
 };
-use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::{
-    colors, commands::checks::ADMIN_CHECK, get_db_handle, say, say_error, utils::resolve_user,
+    admins, audit, colors,
+    commands::checks::{ADMIN_CHECK, MODERATOR_CHECK, OWNER_CHECK, TRUSTED_CHECK},
+    dispatcher, get_db_handle, get_dispatcher, mirror, network,
+    roles::{self, Role},
+    say, say_error, types,
+    utils::{
+        format_timestamp, parse_channel, parse_duration, resolve_single_user, resolve_user,
+        sub_duration,
+    },
 };
 
+/// the permissions the bot needs in a mirror channel, along with their names as shown in discord
+const REQUIRED_PERMISSIONS: &[(Permissions, &str)] = &[
+    (Permissions::READ_MESSAGES, "Read Messages"),
+    (Permissions::SEND_MESSAGES, "Send Messages"),
+    (Permissions::ATTACH_FILES, "Attach Files"),
+    (Permissions::EMBED_LINKS, "Embed Links"),
+];
+
 #[group]
 #[description = "General commands for doing things with the bot"]
-#[commands(enable, disable, user, notify)]
+#[commands(
+    enable,
+    disable,
+    admin,
+    role,
+    block,
+    unblock,
+    blocklist,
+    guildblock,
+    guildunblock,
+    guildblocks,
+    user,
+    notify,
+    networks,
+    configure,
+    queues,
+    shards,
+    audit,
+    deadletters,
+    replay
+)]
 pub struct Utility;
 
 #[command]
@@ -112,15 +150,29 @@ pub fn notify(context: &mut Context, message: &Message, arguments: Args) -> Comm
 
     let mut database = get_db_handle!(context.data.read());
 
-    let channel_iterator = match database.sscan::<&str, u64>("channels") {
-        Ok(iter) => iter,
+    let networks = match database.smembers::<&str, Vec<String>>("networks") {
+        Ok(networks) => networks,
         Err(msg) => {
-            error!("unable to iterate over the mirror channels: {:?}", msg);
+            error!("unable to retrieve the mirror networks: {:?}", msg);
             return Ok(());
         }
     };
 
-    for channel in channel_iterator {
+    // notifications go out to every network
+    let mut channels = Vec::new();
+    for network in networks {
+        match database.smembers::<String, Vec<u64>>(network::channels_key(&network)) {
+            Ok(mut network_channels) => channels.append(&mut network_channels),
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the mirror channels of network {}: {:?}",
+                    network, msg
+                );
+            }
+        }
+    }
+
+    for channel in channels {
         if channel == message.channel_id.0 {
             continue;
         }
@@ -164,6 +216,17 @@ pub fn notify(context: &mut Context, message: &Message, arguments: Args) -> Comm
         }
     }
 
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Notify,
+            target: None,
+            network: None,
+            reason: Some(arguments.message()),
+        },
+    );
+
     say!(
         message,
         context,
@@ -175,42 +238,98 @@ pub fn notify(context: &mut Context, message: &Message, arguments: Args) -> Comm
 }
 
 #[command]
-#[description = "Enables a mirror channel in the server"]
+#[description = "Enables a mirror channel in the server, optionally joining a specific mirror network"]
+#[usage = "[network] [channel]"]
 #[only_in(guilds)]
 #[required_permissions(ADMINISTRATOR)]
 pub fn enable(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
-    // figure out which channel the caller wants us to convert into a mirror channel
-    let channel_id;
-    match arguments.is_empty() {
-        true => channel_id = message.channel_id,
-        false => match arguments.parse::<u64>() {
-            Ok(parsed_channel_id) => channel_id = ChannelId(parsed_channel_id),
-            Err(_) => match arguments.parse::<String>() {
-                Ok(parsed_channel_id) => match ChannelId::from_str(
-                    parsed_channel_id
-                        .trim_end_matches('>')
-                        .trim_start_matches("<#"),
-                ) {
-                    Ok(parsed_channel_id) => channel_id = parsed_channel_id,
-                    Err(_) => {
+    // figure out which channel the caller wants us to convert into a mirror channel, and which
+    // network they want it to be a part of
+    let mut channel_id = message.channel_id;
+    let mut network_name = None;
+    for argument in arguments.raw() {
+        if let Some(parsed_channel_id) = parse_channel(argument) {
+            channel_id = parsed_channel_id;
+        } else if network_name.is_none() {
+            network_name = Some(argument.to_lowercase());
+        } else {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a channel id!"
+            );
+            return Ok(());
+        }
+    }
+    let network_name = network_name.unwrap_or_else(|| String::from(network::DEFAULT));
+
+    if !network::valid_name(&network_name) {
+        say_error!(
+            message,
+            context,
+            format!(
+                "Network names may only contain lowercase letters, digits, dashes and underscores, and must be at most {} characters long!",
+                network::MAX_NAME_LENGTH
+            )
+        );
+        return Ok(());
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::is_guild_banned(&mut *database, &network_name, message.guild_id.unwrap().0) {
+        Ok(false) => (),
+        Ok(true) => {
+            say_error!(
+                message,
+                context,
+                format!("This server is banned from the `{}` network!", network_name)
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to check if a guild is banned: {:?}", msg);
+            say_error!(
+                message,
+                context,
+                "Unable to check if this server is banned!"
+            );
+            return Ok(());
+        }
+    }
+
+    // locked networks can only be joined with the help of a bot admin
+    match database
+        .hget::<String, &str, Option<bool>>(network::settings_key(&network_name), "locked")
+    {
+        Ok(locked) => {
+            if locked.unwrap_or(false) {
+                match roles::of(&mut *database, message.author.id.0) {
+                    Ok(role) if role >= Some(Role::Admin) => (),
+                    Ok(_) => {
                         say_error!(
                             message,
                             context,
-                            "Unable to parse the provided argument to a channel id!"
+                            format!(
+                                "The `{}` network is locked. Ask a bot admin to add your server to it!",
+                                network_name
+                            )
                         );
                         return Ok(());
                     }
-                },
-                Err(_) => {
-                    say_error!(
-                        message,
-                        context,
-                        "Unable to parse the provided argument to a channel id!"
-                    );
-                    return Ok(());
+                    Err(msg) => {
+                        error!("unable to check the role of a user: {:?}", msg);
+                        say_error!(message, context, "Unable to check if you are an admin!");
+                        return Ok(());
+                    }
                 }
-            },
-        },
+            }
+        }
+        Err(msg) => {
+            error!("unable to check if a network is locked: {:?}", msg);
+            say_error!(message, context, "Unable to check the network's settings!");
+            return Ok(());
+        }
     }
 
     // send the initial status message
@@ -218,63 +337,134 @@ pub fn enable(context: &mut Context, message: &Message, arguments: Args) -> Comm
         m.embed(|e| {
             e.title("Enabling")
                 .description(format!(
-                    "Enabling the mirror channel in this server at <#{}>",
-                    channel_id.0
+                    "Enabling the mirror channel in this server at <#{}> on the `{}` network",
+                    channel_id.0, network_name
                 ))
                 .color(colors::PRIMARY)
         })
     })?;
 
-    let mut database = get_db_handle!(context.data.read());
-
     // the guild id is needed in a lot of places. predefine it instead of unwrapping each time we
     // need it
     let guild_id = message.guild_id.unwrap().0;
 
+    // make sure that the channel can actually be used before registering it
+    let problem = match channel_id.to_channel(&context) {
+        Ok(Channel::Guild(channel)) => {
+            let channel = channel.read();
+            if channel.guild_id.0 != guild_id {
+                Some(String::from("That channel is not in this server!"))
+            } else if channel.kind != ChannelType::Text && channel.kind != ChannelType::News {
+                Some(String::from("Mirror channels must be text channels!"))
+            } else {
+                let bot_id = context.cache.read().user.id;
+                match channel.permissions_for_user(&context.cache, bot_id) {
+                    Ok(permissions) => {
+                        let missing = REQUIRED_PERMISSIONS
+                            .iter()
+                            .filter(|(permission, _)| !permissions.contains(*permission))
+                            .map(|(_, name)| format!("`{}`", name))
+                            .collect::<Vec<String>>();
+                        if missing.is_empty() {
+                            None
+                        } else {
+                            Some(format!(
+                                "I am missing the following permissions in <#{}>: {}",
+                                channel_id.0,
+                                missing.join(", ")
+                            ))
+                        }
+                    }
+                    Err(msg) => {
+                        error!(
+                            "unable to compute the permissions of the bot in channel {}: {:?}",
+                            channel_id.0, msg
+                        );
+                        Some(String::from(
+                            "Unable to check my permissions in that channel!",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(_) => Some(String::from("That channel is not in this server!")),
+        Err(msg) => {
+            debug!("unable to get channel {}: {:?}", channel_id.0, msg);
+            Some(String::from("I am unable to see that channel!"))
+        }
+    };
+    if let Some(problem) = problem {
+        status_message.edit(&context, |m| {
+            m.embed(|e| e.title("Error").description(problem).color(colors::ERROR))
+        })?;
+        return Ok(());
+    }
+
     // erase the existing channel from the channel set if necessary
     debug!(
         "checking for an existing mirror channel in guild {}",
         guild_id,
     );
-    match database.hget::<u64, &str, Option<u64>>(guild_id, "mirror_channel") {
-        Ok(chan) => {
-            if let Some(chan) = chan {
+    match network::unlink(&mut *database, guild_id) {
+        Ok(mirror) => {
+            if let Some((chan, network)) = mirror {
                 debug!(
-                    "found an existing mirror channel for guild {} at {}",
-                    guild_id, chan,
+                    "removed the existing mirror channel of guild {} at {} from network {}",
+                    guild_id, chan, network,
                 );
-
-                // remove the existing channel from the channels set
-                match database.srem::<&str, u64, u8>("channels", chan) {
-                    Ok(_) => (),
-                    Err(msg) => {
-                        error!("unable to remove an existing miror channel from the messages set: {:?}", msg);
-                        say_error!(
-                            message,
-                            context,
-                            "Unable to remove the existing mirror channel from the channels set!"
-                        );
-                        return Ok(());
-                    }
-                }
             }
         }
         Err(msg) => {
             error!(
-                "unable to check for an existing mirror channel in a guild: {:?}",
+                "unable to remove an existing mirror channel from its network: {:?}",
                 msg
             );
             say_error!(
                 message,
                 context,
-                "Unable to check for an existing mirror channel!"
+                "Unable to remove the existing mirror channel from its network!"
             );
             return Ok(());
         }
     }
 
+    // mirror messages through a webhook so that they show up under their author's name and
+    // avatar, if the bot is allowed to
+    let webhook = match mirror::obtain_webhook(context, channel_id) {
+        Ok(webhook) => webhook,
+        Err(msg) => {
+            error!(
+                "unable to obtain a webhook in channel {}. falling back to sending as the bot: {:?}",
+                channel_id.0, msg
+            );
+            None
+        }
+    };
+
     // update the guild's top-level hash
-    match database.hset::<u64, &str, u64, bool>(guild_id, "mirror_channel", channel_id.0) {
+    let mut pipeline = redis::pipe();
+    pipeline
+        .atomic()
+        .hset_multiple(
+            guild_id,
+            &[
+                ("mirror_channel", channel_id.0.to_string()),
+                ("network", network_name.clone()),
+            ],
+        )
+        .ignore();
+    match &webhook {
+        Some(webhook) => pipeline.hset_multiple(
+            guild_id,
+            &[
+                ("webhook_id", webhook.id.0.to_string()),
+                ("webhook_token", webhook.token.clone()),
+            ],
+        ),
+        None => pipeline.hdel(guild_id, &["webhook_id", "webhook_token"]),
+    }
+    .ignore();
+    match pipeline.query::<()>(&mut *database) {
         Ok(_) => (),
         Err(msg) => {
             error!(
@@ -290,8 +480,17 @@ pub fn enable(context: &mut Context, message: &Message, arguments: Args) -> Comm
         }
     }
 
-    // update the channel set
-    match database.sadd::<&str, u64, bool>("channels", channel_id.0) {
+    // update the network's channel set and make sure the network is known
+    match redis::pipe()
+        .atomic()
+        .sadd(network::channels_key(&network_name), channel_id.0)
+        .ignore()
+        .sadd("networks", &network_name)
+        .ignore()
+        .hset(network::CHANNEL_GUILDS_KEY, channel_id.0, guild_id)
+        .ignore()
+        .query::<()>(&mut *database)
+    {
         Ok(_) => (),
         Err(msg) => {
             error!(
@@ -307,13 +506,29 @@ pub fn enable(context: &mut Context, message: &Message, arguments: Args) -> Comm
         }
     }
 
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Enable,
+            target: Some(audit::Target::Channel(channel_id.0)),
+            network: Some(&network_name),
+            reason: None,
+        },
+    );
+
     // update the status message one last time
     status_message.edit(&context, |m| {
         m.embed(|e| {
             e.title("Enabling")
                 .description(format!(
-                    "Finished. Try talking in <#{}> and see if anyone replies!",
-                    channel_id.0
+                    "Finished. Try talking in <#{}> and see if anyone replies!{}",
+                    channel_id.0,
+                    if webhook.is_none() {
+                        "\n\n**Note:** I am unable to manage webhooks in that channel, so messages will be mirrored as plain text instead of under their author's name and avatar."
+                    } else {
+                        ""
+                    }
                 ))
                 .color(colors::PRIMARY)
         })
@@ -343,31 +558,30 @@ pub fn disable(context: &mut Context, message: &Message) -> CommandResult {
     // need it
     let guild_id = message.guild_id.unwrap().0;
 
-    match database.hget::<u64, &str, Option<u64>>(guild_id, "mirror_channel") {
-        Ok(chan) => {
-            if let Some(chan) = chan {
-                debug!("found a mirror channel for guild {} at {}", guild_id, chan);
-
-                // remove the channel from the channels set
-                match database.srem::<&str, u64, bool>("channels", chan) {
-                    Ok(_) => (),
-                    Err(msg) => {
-                        error!("unable to disable an existing miror channel from the channels set: {:?}", msg);
-                        say_error!(
-                            message,
-                            context,
-                            "Unable to remove the channel id from the channels set!"
-                        );
-                        return Ok(());
-                    }
-                }
+    match network::unlink(&mut *database, guild_id) {
+        Ok(mirror) => {
+            if let Some((chan, network)) = mirror {
+                debug!(
+                    "removed the mirror channel of guild {} at {} from network {}",
+                    guild_id, chan, network
+                );
+                audit::record(
+                    context,
+                    &message.into(),
+                    &audit::Entry {
+                        action: audit::Action::Disable,
+                        target: Some(audit::Target::Channel(chan)),
+                        network: Some(&network),
+                        reason: None,
+                    },
+                );
 
                 status_message.edit(&context, |m| {
                     m.embed(|e| {
                         e.title("Disabling")
                             .description(format!(
-                                "Disabled your server's mirror channel at <#{}>",
-                                chan
+                                "Disabled your server's mirror channel at <#{}> on the `{}` network",
+                                chan, network
                             ))
                             .color(colors::PRIMARY)
                     })
@@ -380,37 +594,1310 @@ pub fn disable(context: &mut Context, message: &Message) -> CommandResult {
         }
         Err(msg) => {
             error!(
-                "unable to check for an existing mirror channel in a guild: {:?}",
+                "unable to remove a mirror channel from its network: {:?}",
                 msg
             );
             say_error!(
                 message,
                 context,
-                "Unable to check for an existing mirror channel!"
+                "Unable to remove the mirror channel from its network!"
             );
             return Ok(());
         }
     }
 
-    // remove the guild from the top-level key-value store
-    match redis::cmd("UNLINK")
-        .arg(guild_id)
-        .query::<u64>(&mut (*database))
-    {
-        Ok(_) => (),
+    Ok(())
+}
+
+/// the number of blocked users shown on each page of `blocklist`
+const BLOCKS_PER_PAGE: usize = 20;
+
+#[command]
+#[description = "Stops a user's messages from being mirrored into this server"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn block(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
         Err(msg) => {
-            error!(
-                "unable to remove the guild {}'s top-level hash from redis: {:?}",
-                guild_id, msg
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+    let guild_id = message.guild_id.unwrap().0;
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::block_user(&mut *database, guild_id, user_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That user is already blocked!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to block a user: {:?}", msg);
+            say_error!(message, context, "Unable to block that user!");
+            return Ok(());
+        }
+    }
+
+    debug!("guild {} blocked user {}", guild_id, user_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Block,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Blocked `{}`. Their messages will no longer be mirrored into this server",
+            user_id
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lets a blocked user's messages be mirrored into this server again"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn unblock(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+    let guild_id = message.guild_id.unwrap().0;
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::unblock_user(&mut *database, guild_id, user_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That user is not blocked!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to unblock a user: {:?}", msg);
+            say_error!(message, context, "Unable to unblock that user!");
+            return Ok(());
+        }
+    }
+
+    debug!("guild {} unblocked user {}", guild_id, user_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Unblock,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Unblocked `{}`. Their messages will be mirrored into this server again",
+            user_id
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the users whose messages are not mirrored into this server"]
+#[usage = "[page]"]
+#[max_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn blocklist(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let page = if arguments.is_empty() {
+        1
+    } else {
+        match arguments.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided page number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let blocked = match network::blocked_users(&mut *database, message.guild_id.unwrap().0) {
+        Ok(blocked) => blocked,
+        Err(msg) => {
+            error!("unable to retrieve the users blocked by a guild: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the blocked users!");
+            return Ok(());
+        }
+    };
+
+    let count = blocked.len();
+    let lines = blocked
+        .iter()
+        .skip((page - 1) * BLOCKS_PER_PAGE)
+        .take(BLOCKS_PER_PAGE)
+        .map(|(user_id, blocked_at)| {
+            format!(
+                "<@{}> (`{}`), blocked {}",
+                user_id,
+                user_id,
+                format_timestamp(*blocked_at)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let pages = count.div_ceil(BLOCKS_PER_PAGE).max(1);
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Blocked users")
+                .description(if lines.is_empty() {
+                    String::from("There are no blocked users on this page")
+                } else {
+                    lines.join("\n")
+                })
+                .footer(|f| f.text(format!("Page {} of {} ({} total)", page, pages, count)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Manages the moderators and trusted users of the bot"]
+#[usage = "<set|remove|list> [user] [role]"]
+#[sub_commands(role_set, role_remove, role_list)]
+pub fn role(context: &mut Context, message: &Message) -> CommandResult {
+    say_error!(
+        message,
+        context,
+        "Unknown subcommand! Use `set`, `remove` or `list`"
+    );
+    Ok(())
+}
+
+#[command("set")]
+#[description = "Makes a user a moderator or a trusted user, replacing the role they had"]
+#[usage = "<user> <moderator|trusted>"]
+#[num_args(2)]
+#[checks(Admin)]
+pub fn role_set(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+    let role = match Role::from_name(&arguments.single::<String>()?) {
+        Some(role) => role,
+        None => {
+            say_error!(
+                message,
+                context,
+                "Unknown role! Use `moderator` or `trusted`"
             );
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match roles::of(&mut *database, user_id) {
+        Ok(Some(current)) if current >= Role::Admin => {
             say_error!(
                 message,
                 context,
-                "Unable to remove the channel id from the guild hash!"
+                "That user is already an admin or the owner!"
             );
             return Ok(());
         }
+        Ok(_) => (),
+        Err(msg) => {
+            error!("unable to check the role of a user: {:?}", msg);
+            say_error!(message, context, "Unable to check the role of that user!");
+            return Ok(());
+        }
+    }
+
+    if let Err(msg) = roles::assign(&mut *database, user_id, role) {
+        error!("unable to assign a role: {:?}", msg);
+        say_error!(message, context, "Unable to give that user the role!");
+        return Ok(());
+    }
+
+    debug!("made user {} a {}", user_id, role);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::RoleSet,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: Some(role.name()),
+        },
+    );
+
+    message
+        .channel_id
+        .say(&context, format!("Gave `{}` the {} role!", user_id, role))?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Takes away the role of a moderator or trusted user"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[checks(Admin)]
+pub fn role_remove(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match roles::unassign(&mut *database, user_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That user has no role to remove!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to unassign a role: {:?}", msg);
+            say_error!(message, context, "Unable to take away that user's role!");
+            return Ok(());
+        }
     }
 
+    debug!("took away the role of user {}", user_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::RoleRemove,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message
+        .channel_id
+        .say(&context, format!("`{}` no longer has a role!", user_id))?;
+
+    Ok(())
+}
+
+#[command("list")]
+#[description = "Lists the moderators and trusted users of the bot"]
+#[checks(Trusted)]
+pub fn role_list(context: &mut Context, message: &Message) -> CommandResult {
+    let mut database = get_db_handle!(context.data.read());
+
+    let assigned = match roles::assigned(&mut *database) {
+        Ok(assigned) => assigned,
+        Err(msg) => {
+            error!("unable to retrieve the assigned roles: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the roles!");
+            return Ok(());
+        }
+    };
+
+    let fields = Role::ASSIGNABLE
+        .iter()
+        .rev()
+        .map(|role| {
+            let users = assigned
+                .iter()
+                .filter(|(_, assigned)| assigned == role)
+                .map(|(id, _)| format!("<@{}> (`{}`)", id, id))
+                .collect::<Vec<_>>();
+            (
+                role.name(),
+                if users.is_empty() {
+                    String::from("*None*")
+                } else {
+                    users.join("\n")
+                },
+                false,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Roles")
+                .description("Admins are listed with `admin list`")
+                .fields(fields)
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+/// retrieves the name of a guild, asking discord if it isn't cached
+fn guild_name(context: &Context, guild_id: GuildId) -> Option<String> {
+    if let Some(guild) = guild_id.to_guild_cached(context) {
+        return Some(guild.read().name.clone());
+    }
+
+    match context.http.get_guild(guild_id.0) {
+        Ok(guild) => Some(guild.name),
+        Err(msg) => {
+            debug!("unable to retrieve guild {}: {:?}", guild_id.0, msg);
+            None
+        }
+    }
+}
+
+#[command]
+#[description = "Stops messages sent from another server from being mirrored into this server"]
+#[usage = "<server id>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn guildblock(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let blocked_id = match arguments.single::<u64>() {
+        Ok(guild_id) => guild_id,
+        Err(_) => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a server id!"
+            );
+            return Ok(());
+        }
+    };
+    let guild_id = message.guild_id.unwrap().0;
+
+    if blocked_id == guild_id {
+        say_error!(message, context, "You cannot block this server!");
+        return Ok(());
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::block_guild(&mut *database, guild_id, blocked_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That server is already blocked!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to block a guild: {:?}", msg);
+            say_error!(message, context, "Unable to block that server!");
+            return Ok(());
+        }
+    }
+
+    debug!("guild {} blocked guild {}", guild_id, blocked_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::GuildBlock,
+            target: Some(audit::Target::Guild(blocked_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Blocked server `{}`. Messages sent from it will no longer be mirrored into this server",
+            blocked_id
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lets messages sent from a blocked server be mirrored into this server again"]
+#[usage = "<server id>"]
+#[num_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn guildunblock(
+    context: &mut Context,
+    message: &Message,
+    mut arguments: Args,
+) -> CommandResult {
+    let blocked_id = match arguments.single::<u64>() {
+        Ok(guild_id) => guild_id,
+        Err(_) => {
+            say_error!(
+                message,
+                context,
+                "Unable to parse the provided argument to a server id!"
+            );
+            return Ok(());
+        }
+    };
+    let guild_id = message.guild_id.unwrap().0;
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match network::unblock_guild(&mut *database, guild_id, blocked_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That server is not blocked!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to unblock a guild: {:?}", msg);
+            say_error!(message, context, "Unable to unblock that server!");
+            return Ok(());
+        }
+    }
+
+    debug!("guild {} unblocked guild {}", guild_id, blocked_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::GuildUnblock,
+            target: Some(audit::Target::Guild(blocked_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message.channel_id.say(
+        &context,
+        format!(
+            "Unblocked server `{}`. Messages sent from it will be mirrored into this server again",
+            blocked_id
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the servers whose messages are not mirrored into this server"]
+#[usage = "[page]"]
+#[max_args(1)]
+#[only_in(guilds)]
+#[required_permissions(ADMINISTRATOR)]
+pub fn guildblocks(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let page = if arguments.is_empty() {
+        1
+    } else {
+        match arguments.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided page number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let blocked = match network::blocked_guilds(&mut *database, message.guild_id.unwrap().0) {
+        Ok(blocked) => blocked,
+        Err(msg) => {
+            error!(
+                "unable to retrieve the guilds blocked by a guild: {:?}",
+                msg
+            );
+            say_error!(message, context, "Unable to retrieve the blocked servers!");
+            return Ok(());
+        }
+    };
+
+    let count = blocked.len();
+    let lines = blocked
+        .iter()
+        .skip((page - 1) * BLOCKS_PER_PAGE)
+        .take(BLOCKS_PER_PAGE)
+        .map(|guild_id| match guild_name(context, GuildId(*guild_id)) {
+            Some(name) => format!("**{}** (`{}`)", name, guild_id),
+            None => format!("*Unknown server* (`{}`)", guild_id),
+        })
+        .collect::<Vec<_>>();
+
+    let pages = count.div_ceil(BLOCKS_PER_PAGE).max(1);
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Blocked servers")
+                .description(if lines.is_empty() {
+                    String::from("There are no blocked servers on this page")
+                } else {
+                    lines.join("\n")
+                })
+                .footer(|f| f.text(format!("Page {} of {} ({} total)", page, pages, count)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Manages the admins of the bot. Admins listed in the configuration can only be removed from there"]
+#[usage = "<add|remove|list> [user]"]
+#[checks(Owner)]
+// admins from the configuration are owners as far as the framework is concerned, which would
+// let them skip the check
+#[owner_privilege(false)]
+#[sub_commands(admin_add, admin_remove, admin_list)]
+pub fn admin(context: &mut Context, message: &Message) -> CommandResult {
+    say_error!(
+        message,
+        context,
+        "Unknown subcommand! Use `add`, `remove` or `list`"
+    );
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Makes a user an admin of the bot"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[checks(Owner)]
+#[owner_privilege(false)]
+pub fn admin_add(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match admins::add(&mut *database, user_id) {
+        Ok(true) => (),
+        Ok(false) => {
+            say_error!(message, context, "That user is already an admin!");
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to add an admin: {:?}", msg);
+            say_error!(message, context, "Unable to make that user an admin!");
+            return Ok(());
+        }
+    }
+
+    debug!("made user {} an admin", user_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::AdminAdd,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message
+        .channel_id
+        .say(&context, format!("Made `{}` an admin!", user_id))?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Takes away a user's admin status"]
+#[usage = "<user>"]
+#[num_args(1)]
+#[checks(Owner)]
+#[owner_privilege(false)]
+pub fn admin_remove(
+    context: &mut Context,
+    message: &Message,
+    mut arguments: Args,
+) -> CommandResult {
+    let user_id = match resolve_single_user(context, &arguments.single_quoted::<String>()?) {
+        Ok(id) => id,
+        Err(msg) => {
+            say_error!(message, context, msg);
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match admins::remove(&mut *database, user_id) {
+        Ok(admins::Removal::Removed) => (),
+        Ok(admins::Removal::NotAdmin) => {
+            say_error!(message, context, "That user is not an admin!");
+            return Ok(());
+        }
+        Ok(admins::Removal::Pinned) => {
+            say_error!(
+                message,
+                context,
+                "That user is an admin because of the configuration, and has to be removed from there!"
+            );
+            return Ok(());
+        }
+        Err(msg) => {
+            error!("unable to remove an admin: {:?}", msg);
+            say_error!(
+                message,
+                context,
+                "Unable to take away that user's admin status!"
+            );
+            return Ok(());
+        }
+    }
+
+    debug!("took away the admin status of user {}", user_id);
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::AdminRemove,
+            target: Some(audit::Target::User(user_id)),
+            network: None,
+            reason: None,
+        },
+    );
+
+    message
+        .channel_id
+        .say(&context, format!("`{}` is no longer an admin!", user_id))?;
+
+    Ok(())
+}
+
+#[command("list")]
+#[description = "Lists the admins of the bot"]
+#[checks(Owner)]
+#[owner_privilege(false)]
+pub fn admin_list(context: &mut Context, message: &Message) -> CommandResult {
+    let mut database = get_db_handle!(context.data.read());
+
+    let (pinned, runtime) = match admins::list(&mut *database) {
+        Ok(admins) => admins,
+        Err(msg) => {
+            error!("unable to retrieve the admins: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the admins!");
+            return Ok(());
+        }
+    };
+
+    let describe = |admins: &[u64]| {
+        if admins.is_empty() {
+            String::from("*None*")
+        } else {
+            admins
+                .iter()
+                .map(|id| format!("<@{}> (`{}`)", id, id))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Admins")
+                .field("From the configuration", describe(&pinned), false)
+                .field("Added with `admin add`", describe(&runtime), false)
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the mirror networks that servers can join"]
+pub fn networks(context: &mut Context, message: &Message) -> CommandResult {
+    let mut database = get_db_handle!(context.data.read());
+
+    let mut networks = match database.smembers::<&str, Vec<String>>("networks") {
+        Ok(networks) => networks,
+        Err(msg) => {
+            error!("unable to retrieve the mirror networks: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the mirror networks!");
+            return Ok(());
+        }
+    };
+    networks.sort();
+
+    let mut fields = Vec::with_capacity(networks.len());
+    for network in networks {
+        let (description, locked) = match database
+            .hget::<String, &[&str], (Option<String>, Option<bool>)>(
+                network::settings_key(&network),
+                &["description", "locked"],
+            ) {
+            Ok(settings) => settings,
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the settings of network {}: {:?}",
+                    network, msg
+                );
+                (None, None)
+            }
+        };
+        let channels = match database.scard::<String, u64>(network::channels_key(&network)) {
+            Ok(channels) => channels,
+            Err(msg) => {
+                error!(
+                    "unable to count the channels of network {}: {:?}",
+                    network, msg
+                );
+                0
+            }
+        };
+
+        fields.push((
+            if locked.unwrap_or(false) {
+                format!("{} (locked)", network)
+            } else {
+                network
+            },
+            format!(
+                "{}\n**Servers:** {}",
+                description.unwrap_or_else(|| String::from("No description")),
+                channels
+            ),
+            false,
+        ));
+    }
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Networks")
+                .description("Join one of these with the `enable` command")
+                .fields(fields)
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Changes the settings of a mirror network. Available settings are `description`, `locked` and `modlog` (a channel that moderation actions in the network are posted to, or `none`)"]
+#[usage = "<network> <setting> <value>"]
+#[min_args(3)]
+#[checks(Admin)]
+pub fn configure(context: &mut Context, message: &Message, mut arguments: Args) -> CommandResult {
+    let network_name = arguments.single::<String>()?.to_lowercase();
+    let setting = arguments.single::<String>()?.to_lowercase();
+    let value = arguments.rest();
+
+    if !network::valid_name(&network_name) {
+        say_error!(message, context, "That is not a valid network name!");
+        return Ok(());
+    }
+
+    let value = match setting.as_str() {
+        "description" => Some(value.to_string()),
+        "locked" => match value.parse::<bool>() {
+            Ok(locked) => Some(locked.to_string()),
+            Err(_) => {
+                say_error!(message, context, "`locked` must be `true` or `false`!");
+                return Ok(());
+            }
+        },
+        "modlog" => match parse_channel(value) {
+            Some(channel_id) => Some(channel_id.0.to_string()),
+            None if value.eq_ignore_ascii_case("none") => None,
+            None => {
+                say_error!(message, context, "`modlog` must be a channel or `none`!");
+                return Ok(());
+            }
+        },
+        _ => {
+            say_error!(message, context, "That is not a network setting!");
+            return Ok(());
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let mut pipeline = redis::pipe();
+    pipeline.atomic();
+    match &value {
+        Some(value) => pipeline.hset(network::settings_key(&network_name), &setting, value),
+        None => pipeline.hdel(network::settings_key(&network_name), &setting),
+    }
+    .ignore();
+    match pipeline
+        .sadd("networks", &network_name)
+        .ignore()
+        .query::<()>(&mut *database)
+    {
+        Ok(_) => (),
+        Err(msg) => {
+            error!("unable to update the settings of a network: {:?}", msg);
+            say_error!(message, context, "Unable to update the network's settings!");
+            return Ok(());
+        }
+    }
+
+    audit::record(
+        context,
+        &message.into(),
+        &audit::Entry {
+            action: audit::Action::Configure,
+            target: None,
+            network: Some(&network_name),
+            reason: Some(&match &value {
+                Some(value) => format!("Set `{}` to `{}`", setting, value),
+                None => format!("Unset `{}`", setting),
+            }),
+        },
+    );
+
+    say!(
+        message,
+        context,
+        "Configure",
+        match value {
+            Some(value) => format!(
+                "Set `{}` to `{}` on the `{}` network",
+                setting, value, network_name
+            ),
+            None => format!("Unset `{}` on the `{}` network", setting, network_name),
+        }
+    );
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the state of the queues used to deliver mirrored messages"]
+#[checks(Admin)]
+pub fn queues(context: &mut Context, message: &Message) -> CommandResult {
+    let mut queues = get_dispatcher!(context.data.read()).snapshot();
+    queues.sort_by(|a, b| {
+        b.depth
+            .cmp(&a.depth)
+            .then(b.average_latency.cmp(&a.average_latency))
+    });
+
+    let depth: usize = queues.iter().map(|queue| queue.depth).sum();
+    let processed: u64 = queues.iter().map(|queue| queue.processed).sum();
+
+    let mut busiest = queues
+        .iter()
+        .take(10)
+        .map(|queue| {
+            format!(
+                "<#{}>: **{}** queued, {} delivered, {}ms average, {}ms max",
+                queue.channel.0,
+                queue.depth,
+                queue.processed,
+                queue.average_latency.as_millis(),
+                queue.max_latency.as_millis()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if busiest.is_empty() {
+        busiest = String::from("No channels have active workers");
+    }
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Queues")
+                .description(format!(
+                    "**Workers:** {}\n**Queued jobs:** {}\n**Delivered jobs:** {}",
+                    queues.len(),
+                    depth,
+                    processed
+                ))
+                .field("Busiest channels", busiest, false)
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the state of the bot's shards"]
+#[checks(Admin)]
+pub fn shards(context: &mut Context, message: &Message) -> CommandResult {
+    let shard_manager = match context.data.read().get::<types::ShardManager>() {
+        Some(shard_manager) => Arc::clone(shard_manager),
+        None => {
+            panic!("no shard manager was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+
+    let mut runners = shard_manager
+        .lock()
+        .runners
+        .lock()
+        .iter()
+        .map(|(id, runner)| (id.0, runner.stage, runner.latency))
+        .collect::<Vec<_>>();
+    runners.sort_by_key(|(id, _, _)| *id);
+
+    let guilds = context
+        .cache
+        .read()
+        .guilds
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let shard_count = context.cache.read().shard_count.max(1);
+    let lines = runners
+        .iter()
+        .map(|(id, stage, latency)| {
+            format!(
+                "**Shard {}**{}: {}, {} latency, {} servers",
+                id,
+                if *id == context.shard_id {
+                    " (this one)"
+                } else {
+                    ""
+                },
+                stage,
+                latency
+                    .map(|latency| format!("{}ms", latency.as_millis()))
+                    .unwrap_or_else(|| String::from("unknown")),
+                guilds
+                    .iter()
+                    .filter(|guild| (guild.0 >> 22) % shard_count == *id)
+                    .count()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Shards")
+                .description(format!("**Shards:** {}\n\n{}", runners.len(), lines))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+/// how many audit log entries are shown per page
+const AUDIT_ENTRIES_PER_PAGE: usize = 10;
+
+/// what page numbers passed to `audit` start with, so that they can't be mistaken for ids
+const AUDIT_PAGE_PREFIX: &str = "page:";
+
+#[command]
+#[description = "Searches the audit log, newest first. Entries can be narrowed down to a user, server or channel id, an action (e.g. `ban`, `enable`) and how far back to go (e.g. `2h`, `7d`). Later pages are shown with `page:<number>`"]
+#[usage = "[user|server|action] [since] [page:<number>]"]
+#[checks(Moderator)]
+pub fn audit(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let mut action = None;
+    let mut id = None;
+    let mut since = None;
+    let mut page = 1;
+    for argument in arguments.raw() {
+        let stripped = argument
+            .trim_end_matches('>')
+            .trim_start_matches("<@")
+            .trim_start_matches("<#")
+            .trim_start_matches('!');
+
+        if let Some(number) = argument.strip_prefix(AUDIT_PAGE_PREFIX) {
+            match number.parse::<usize>() {
+                Ok(number) if number > 0 => page = number,
+                _ => {
+                    say_error!(
+                        message,
+                        context,
+                        "Unable to parse the provided page number!"
+                    );
+                    return Ok(());
+                }
+            }
+        } else if let Some(parsed_action) = audit::Action::from_name(argument) {
+            action = Some(parsed_action);
+        } else if let Some(duration) = parse_duration(argument) {
+            // durations are bounded, so this only falls back to no limit if the clock is
+            // wildly off
+            since = sub_duration(Utc::now().timestamp(), duration);
+        } else if let Ok(number) = stripped.parse::<u64>() {
+            id = Some(number);
+        } else {
+            say_error!(
+                message,
+                context,
+                format!(
+                    "Unable to parse `{}`! Expected an id, a duration, `{}<number>` or one of the following actions: {}",
+                    argument,
+                    AUDIT_PAGE_PREFIX,
+                    audit::Action::ALL
+                        .iter()
+                        .map(|action| format!("`{}`", action))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            );
+            return Ok(());
+        }
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let mut records = match audit::search(
+        &mut *database,
+        |record| {
+            action.is_none_or(|action| record.action == action)
+                && id.is_none_or(|id| record.involves(id))
+        },
+        since,
+        (page - 1) * AUDIT_ENTRIES_PER_PAGE,
+        AUDIT_ENTRIES_PER_PAGE + 1,
+    ) {
+        Ok(records) => records,
+        Err(msg) => {
+            error!("unable to search the audit log: {:?}", msg);
+            say_error!(message, context, "Unable to search the audit log!");
+            return Ok(());
+        }
+    };
+
+    // one more record than is shown is looked for to know if there is another page
+    let more = records.len() > AUDIT_ENTRIES_PER_PAGE;
+    records.truncate(AUDIT_ENTRIES_PER_PAGE);
+
+    let fields = records
+        .into_iter()
+        .map(|(timestamp, record)| {
+            let mut value = format!(
+                "**Actor:** <@{}>\n**When:** {}",
+                record.actor_id,
+                format_timestamp(timestamp)
+            );
+            if let Some(target) = record.target {
+                value.push_str(&format!("\n**Target:** {}", target));
+            }
+            value.push_str(&format!(
+                "\n**Network:** {}",
+                record.network.as_deref().unwrap_or("All networks")
+            ));
+            if let Some(reason) = &record.reason {
+                value.push_str(&format!("\n**Reason:** {}", reason));
+            }
+            if let Some(guild_id) = record.guild_id {
+                value.push_str(&format!(
+                    "\n[Jump to the command](https://discordapp.com/channels/{}/{}/{})",
+                    guild_id, record.channel_id, record.message_id
+                ));
+            }
+
+            (record.action.to_string(), value, false)
+        })
+        .collect::<Vec<_>>();
+
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Audit log")
+                .description(if fields.is_empty() {
+                    String::from("No entries matched on this page")
+                } else if more {
+                    format!(
+                        "Add `{}{}` to the command to see the next page",
+                        AUDIT_PAGE_PREFIX,
+                        page + 1
+                    )
+                } else {
+                    String::from("This is the last page")
+                })
+                .fields(fields)
+                .footer(|f| f.text(format!("Page {}", page)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+/// how many dead letters are shown per page
+const DEAD_LETTERS_PER_PAGE: usize = 10;
+
+#[command]
+#[description = "Lists mirror deliveries that permanently failed"]
+#[usage = "[page]"]
+#[max_args(1)]
+#[checks(Admin)]
+pub fn deadletters(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let page = if arguments.is_empty() {
+        1
+    } else {
+        match arguments.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided page number!"
+                );
+                return Ok(());
+            }
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let count = match dispatcher::count_dead_letters(&mut *database) {
+        Ok(count) => count,
+        Err(msg) => {
+            error!("unable to count the dead letters: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the dead letters!");
+            return Ok(());
+        }
+    };
+
+    let dead_letters = match dispatcher::dead_letters(
+        &mut *database,
+        (page - 1) * DEAD_LETTERS_PER_PAGE,
+        DEAD_LETTERS_PER_PAGE,
+    ) {
+        Ok(dead_letters) => dead_letters,
+        Err(msg) => {
+            error!("unable to retrieve the dead letters: {:?}", msg);
+            say_error!(message, context, "Unable to retrieve the dead letters!");
+            return Ok(());
+        }
+    };
+
+    let fields = dead_letters
+        .into_iter()
+        .map(|(id, dead_letter)| {
+            (
+                format!("#{}: {}", id, dead_letter.job.describe()),
+                format!(
+                    "**Channel:** <#{}>\n**Failed:** {}\n**Error:** {}",
+                    dead_letter.channel_id,
+                    format_timestamp(dead_letter.failed_at),
+                    dead_letter.error
+                ),
+                false,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let pages = count.div_ceil(DEAD_LETTERS_PER_PAGE).max(1);
+    message.channel_id.send_message(&context, |m| {
+        m.embed(|e| {
+            e.title("Dead letters")
+                .description(if fields.is_empty() {
+                    String::from("There are no dead letters on this page")
+                } else {
+                    String::from("Replay one with `replay <id>`, or all of them with `replay all`")
+                })
+                .fields(fields)
+                .footer(|f| f.text(format!("Page {} of {} ({} total)", page, pages, count)))
+                .color(colors::PRIMARY)
+        })
+    })?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Retries mirror deliveries that permanently failed"]
+#[usage = "<id|all>"]
+#[num_args(1)]
+#[checks(Admin)]
+pub fn replay(context: &mut Context, message: &Message, arguments: Args) -> CommandResult {
+    let mut database = get_db_handle!(context.data.read());
+
+    let dead_letters = if arguments.current() == Some("all") {
+        match dispatcher::take_dead_letters(&mut *database) {
+            Ok(dead_letters) => dead_letters,
+            Err(msg) => {
+                error!("unable to retrieve the dead letters: {:?}", msg);
+                say_error!(message, context, "Unable to retrieve the dead letters!");
+                return Ok(());
+            }
+        }
+    } else {
+        let id = match arguments.parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => {
+                say_error!(
+                    message,
+                    context,
+                    "Unable to parse the provided argument to a dead letter id!"
+                );
+                return Ok(());
+            }
+        };
+
+        match dispatcher::take_dead_letter(&mut *database, id) {
+            Ok(Some(dead_letter)) => vec![dead_letter],
+            Ok(None) => {
+                say_error!(message, context, "There is no dead letter with that id!");
+                return Ok(());
+            }
+            Err(msg) => {
+                error!("unable to retrieve a dead letter: {:?}", msg);
+                say_error!(message, context, "Unable to retrieve the dead letter!");
+                return Ok(());
+            }
+        }
+    };
+
+    drop(database);
+
+    let replayed = dead_letters.len();
+    let dispatcher = get_dispatcher!(context.data.read());
+    for dead_letter in dead_letters {
+        dispatcher.dispatch(context, ChannelId(dead_letter.channel_id), dead_letter.job);
+    }
+
+    say!(
+        message,
+        context,
+        "Replay",
+        format!("Queued {} failed deliveries to be retried.", replayed)
+    );
+
     Ok(())
 }
@@ -18,7 +18,7 @@
 
 use log::LevelFilter;
 
-use crate::types::DatabaseConfig;
+use crate::types::{DatabaseConfig, StrikeConfig};
 
 #[inline(always)]
 pub fn prefix() -> String {
@@ -35,11 +35,50 @@ pub fn log_level() -> LevelFilter {
     LevelFilter::Info
 }
 
+#[inline(always)]
+pub fn unlink_threshold() -> u64 {
+    5
+}
+
+#[inline(always)]
+pub fn strike_configuration() -> StrikeConfig {
+    StrikeConfig {
+        mute_threshold: strikes_mute_threshold(),
+        mute_duration: strikes_mute_duration(),
+        ban_threshold: strikes_ban_threshold(),
+        ban_duration: None,
+    }
+}
+
+#[inline(always)]
+pub fn strikes_mute_threshold() -> usize {
+    3
+}
+
+#[inline(always)]
+pub fn strikes_mute_duration() -> u64 {
+    // one day
+    86_400
+}
+
+#[inline(always)]
+pub fn strikes_ban_threshold() -> usize {
+    5
+}
+
+#[inline(always)]
+pub fn appeal_cooldown() -> u64 {
+    // one week
+    604_800
+}
+
 #[inline(always)]
 pub fn database_configuration() -> DatabaseConfig {
     DatabaseConfig {
         host: database_host(),
         max_connections: database_max_connections(),
+        mirror_retention: database_mirror_retention(),
+        audit_retention: database_audit_retention(),
     }
 }
 
@@ -48,6 +87,17 @@ pub fn database_max_connections() -> u32 {
     20
 }
 
+#[inline(always)]
+pub fn database_mirror_retention() -> usize {
+    // one week
+    604_800
+}
+
+#[inline(always)]
+pub fn database_audit_retention() -> usize {
+    100_000
+}
+
 #[inline(always)]
 pub fn database_host() -> String {
     String::from("redis://localhost")
@@ -0,0 +1,820 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use r2d2_redis::redis::{self, Commands, ConnectionLike, PipelineCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    model::id::ChannelId, prelude::*, Error as SerenityError, Result as SerenityResult,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    get_db_handle,
+    mirror::{self, Outgoing, Replica},
+    network, types,
+    utils::{is_dead_channel, is_transient},
+};
+
+/// how long a worker waits for a new job before shutting itself down
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// jobs that take longer than this to complete (including the time spent queued) are logged
+const SLOW_JOB: Duration = Duration::from_secs(10);
+
+/// queues deeper than this are logged whenever something is added to them
+const DEEP_QUEUE: usize = 50;
+
+/// how many times a job is attempted before it is given up on
+const MAX_ATTEMPTS: u32 = 5;
+
+/// how long to wait before retrying a job for the first time. this doubles with every attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// the most jobs that are kept in the dead letter list. the oldest are dropped first
+const DEAD_LETTER_LIMIT: isize = 1000;
+
+/// the key of the hash holding jobs that permanently failed, keyed by their id
+const DEAD_LETTERS_KEY: &str = "deadletters-entries";
+
+/// the key of the list holding the ids of the dead letters, oldest first
+const DEAD_LETTER_IDS_KEY: &str = "deadletters-ids";
+
+/// the key of the counter that dead letter ids are taken from
+const DEAD_LETTER_COUNTER_KEY: &str = "deadletters-counter";
+
+/// records a dead letter under a new id, dropping the oldest ones beyond the limit
+const BURY: &str = r#"
+local id = redis.call("INCR", KEYS[1])
+redis.call("HSET", KEYS[2], id, ARGV[1])
+redis.call("RPUSH", KEYS[3], id)
+while redis.call("LLEN", KEYS[3]) > tonumber(ARGV[2]) do
+    redis.call("HDEL", KEYS[2], redis.call("LPOP", KEYS[3]))
+end
+return id
+"#;
+
+/// the redis channel that jobs for guilds on shards run by other processes are published to
+const JOBS_CHANNEL: &str = "jobs";
+
+/// how long to wait before resubscribing to the jobs channel after losing the connection to it
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// how often a process announces that it is receiving the jobs of its shards
+const PRESENCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// how many seconds an announcement lasts. shards that aren't announced within this time are
+/// treated as having no process running them
+const PRESENCE_TTL: usize = 30;
+
+/// the key that exists while a process is receiving the jobs of a shard
+fn presence_key(shard: u64) -> String {
+    format!("shard-{}-presence", shard)
+}
+
+/// a unit of work performed against a single mirror channel
+#[derive(Serialize, Deserialize)]
+pub enum Job {
+    /// mirror a message into the channel
+    Send {
+        message_id: u64,
+        outgoing: Arc<Outgoing>,
+    },
+
+    /// edit a copy of a mirrored message to reflect changes made to the original
+    Edit {
+        replica: Replica,
+        outgoing: Arc<Outgoing>,
+    },
+
+    /// delete a copy of a mirrored message
+    Delete { replica: Replica },
+}
+
+impl Job {
+    /// describes the job for humans
+    pub fn describe(&self) -> String {
+        match self {
+            Job::Send { message_id, .. } => format!("mirror message {}", message_id),
+            Job::Edit { replica, .. } => format!("edit copy {}", replica.message_id),
+            Job::Delete { replica } => format!("delete copy {}", replica.message_id),
+        }
+    }
+}
+
+/// a job that permanently failed, kept around so that it can be inspected and replayed
+#[derive(Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub channel_id: u64,
+    pub job: Job,
+
+    /// the error the job failed with on its last attempt
+    pub error: String,
+
+    /// the unix timestamp of when the job was given up on
+    pub failed_at: i64,
+}
+
+/// a job published for the process running the shard of the guild it is for. attachments are
+/// not sent along, so copies delivered this way link to them instead
+#[derive(Serialize, Deserialize)]
+struct Delivery {
+    channel_id: u64,
+    guild_id: u64,
+    job: Job,
+}
+
+/// a job along with everything needed to perform it
+struct Envelope {
+    context: Context,
+    job: Job,
+    queued_at: Instant,
+}
+
+/// counters describing the state of a single worker's queue
+#[derive(Default)]
+struct Statistics {
+    depth: AtomicUsize,
+    processed: AtomicU64,
+    total_latency_ms: AtomicU64,
+    max_latency_ms: AtomicU64,
+}
+
+/// a handle to the thread performing jobs for a single mirror channel
+struct Worker {
+    sender: mpsc::Sender<Envelope>,
+    statistics: Arc<Statistics>,
+}
+
+/// a snapshot of the state of a single worker's queue
+pub struct QueueSnapshot {
+    pub channel: ChannelId,
+
+    /// how many jobs are waiting to be or are being performed
+    pub depth: usize,
+
+    /// how many jobs have been performed
+    pub processed: u64,
+
+    /// the average time between a job being queued and it being completed
+    pub average_latency: Duration,
+
+    /// the longest time between a job being queued and it being completed
+    pub max_latency: Duration,
+}
+
+/// fans jobs out to a worker per mirror channel, so that a slow or ratelimited channel only
+/// delays itself. serenity's http client already honors discord's per-route ratelimits by
+/// blocking the calling thread, which here is only ever the worker of the affected channel
+#[derive(Default)]
+pub struct Dispatcher {
+    workers: Mutex<HashMap<ChannelId, Worker>>,
+    subscribed: AtomicBool,
+
+    /// whether the subscription to the jobs channel is currently up
+    receiving: AtomicBool,
+}
+
+impl Dispatcher {
+    /// queues a job to be performed against a mirror channel, or publishes it if the channel's
+    /// guild is on a shard run by another process
+    pub fn dispatch(self: &Arc<Self>, context: &Context, channel: ChannelId, job: Job) {
+        let guild_id = match &job {
+            Job::Send { .. } => {
+                let mut database = get_db_handle!(context.data.read());
+                match database
+                    .hget::<&str, u64, Option<u64>>(network::CHANNEL_GUILDS_KEY, channel.0)
+                {
+                    Ok(guild_id) => guild_id,
+                    Err(msg) => {
+                        error!(
+                            "unable to look up the guild of channel {}: {:?}",
+                            channel.0, msg
+                        );
+                        None
+                    }
+                }
+            }
+            Job::Edit { replica, .. } | Job::Delete { replica } => Some(replica.guild_id),
+        };
+
+        let guild_id = match guild_id {
+            Some(guild_id) if !owns(context, guild_id) => guild_id,
+            _ => return self.enqueue(context, channel, job),
+        };
+
+        // a published job is dropped by every process if the one running the guild's shard is
+        // down, so it is only published while that process is announcing itself
+        match is_present(context, guild_id) {
+            Ok(true) => (),
+            Ok(false) => {
+                warn!(
+                    "no process is running the shard of channel {}. performing the job here",
+                    channel.0
+                );
+                return self.enqueue(context, channel, job);
+            }
+            Err(msg) => {
+                error!(
+                    "unable to check if the shard of channel {} is running. performing the job here: {:?}",
+                    channel.0, msg
+                );
+                return self.enqueue(context, channel, job);
+            }
+        }
+
+        let delivery = Delivery {
+            channel_id: channel.0,
+            guild_id,
+            job,
+        };
+        let encoded = match rmp_serde::to_vec_named(&delivery) {
+            Ok(encoded) => encoded,
+            Err(msg) => {
+                error!("unable to encode a job for another process: {:?}", msg);
+                return;
+            }
+        };
+
+        let mut database = get_db_handle!(context.data.read());
+        match database.publish::<&str, Vec<u8>, u64>(JOBS_CHANNEL, encoded) {
+            Ok(0) => warn!(
+                "no process received the job for channel {}. performing it here",
+                channel.0
+            ),
+            Ok(_) => return,
+            Err(msg) => error!(
+                "unable to publish the job for channel {}. performing it here: {:?}",
+                channel.0, msg
+            ),
+        }
+
+        // any process can deliver to any channel, it is just not spread out as evenly
+        self.enqueue(context, channel, delivery.job);
+    }
+
+    /// starts receiving the jobs published by other processes, if this process only runs some
+    /// of the bot's shards. this only does anything the first time it is called
+    pub fn subscribe(self: &Arc<Self>, context: &Context) {
+        let host = match context.data.read().get::<types::Configuration>() {
+            Some(cfg) if cfg.shard_range.is_some() => cfg.database.host.clone(),
+            Some(_) => return,
+            None => panic!(
+                "no configuration was stored inside of the data TypeMap (this is a severe bug)"
+            ),
+        };
+        if self.subscribed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let dispatcher = Arc::clone(self);
+        let subscriber_context = context.clone();
+        if let Err(msg) = thread::Builder::new()
+            .name(String::from("dispatcher-subscriber"))
+            .spawn(move || loop {
+                if let Err(msg) = dispatcher.receive(&subscriber_context, &host) {
+                    error!(
+                        "lost the subscription to the jobs channel, resubscribing in {:?}: {:?}",
+                        RESUBSCRIBE_DELAY, msg
+                    );
+                }
+                dispatcher.receiving.store(false, Ordering::SeqCst);
+                thread::sleep(RESUBSCRIBE_DELAY);
+            })
+        {
+            error!("unable to subscribe to the jobs channel: {:?}", msg);
+            self.subscribed.store(false, Ordering::SeqCst);
+            return;
+        }
+
+        let dispatcher = Arc::clone(self);
+        let context = context.clone();
+        if let Err(msg) = thread::Builder::new()
+            .name(String::from("dispatcher-presence"))
+            .spawn(move || loop {
+                if dispatcher.receiving.load(Ordering::SeqCst) {
+                    if let Err(msg) = announce(&context) {
+                        error!("unable to announce the shards of this process: {:?}", msg);
+                    }
+                }
+                thread::sleep(PRESENCE_INTERVAL);
+            })
+        {
+            error!(
+                "unable to start announcing the shards of this process: {:?}",
+                msg
+            );
+        }
+    }
+
+    /// performs the published jobs that belong to this process until the connection fails
+    fn receive(self: &Arc<Self>, context: &Context, host: &str) -> RedisResult<()> {
+        // subscribed connections can't be used for anything else, so this one isn't pooled
+        let mut connection = redis::Client::open(host)?.get_connection()?;
+        let mut pubsub = connection.as_pubsub();
+        pubsub.subscribe(JOBS_CHANNEL)?;
+        info!("subscribed to the jobs channel");
+
+        self.receiving.store(true, Ordering::SeqCst);
+        if let Err(msg) = announce(context) {
+            error!("unable to announce the shards of this process: {:?}", msg);
+        }
+
+        loop {
+            let message = pubsub.get_message()?;
+            let delivery = match rmp_serde::from_slice::<Delivery>(message.get_payload_bytes()) {
+                Ok(delivery) => delivery,
+                Err(msg) => {
+                    error!("unable to decode a job from another process: {:?}", msg);
+                    continue;
+                }
+            };
+
+            if owns(context, delivery.guild_id) {
+                self.enqueue(context, ChannelId(delivery.channel_id), delivery.job);
+            }
+        }
+    }
+
+    /// queues a job to be performed against a mirror channel, starting a worker for the channel
+    /// if it doesn't have one
+    pub fn enqueue(self: &Arc<Self>, context: &Context, channel: ChannelId, job: Job) {
+        let mut envelope = Envelope {
+            context: context.clone(),
+            job,
+            queued_at: Instant::now(),
+        };
+        let mut workers = self.workers.lock().unwrap();
+
+        loop {
+            let worker = workers
+                .entry(channel)
+                .or_insert_with(|| self.spawn_worker(channel));
+
+            let depth = worker.statistics.depth.fetch_add(1, Ordering::SeqCst) + 1;
+            match worker.sender.send(envelope) {
+                Ok(_) => {
+                    if depth > DEEP_QUEUE {
+                        warn!("the queue of channel {} is {} jobs deep", channel.0, depth);
+                    }
+                    return;
+                }
+                Err(mpsc::SendError(returned)) => {
+                    // the worker died without removing itself, so replace it
+                    error!(
+                        "the worker for channel {} stopped unexpectedly. restarting it",
+                        channel.0
+                    );
+                    workers.remove(&channel);
+                    envelope = returned;
+                }
+            }
+        }
+    }
+
+    /// retrieves the state of every worker's queue
+    pub fn snapshot(&self) -> Vec<QueueSnapshot> {
+        self.workers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(channel, worker)| {
+                let statistics = &worker.statistics;
+                let processed = statistics.processed.load(Ordering::SeqCst);
+                QueueSnapshot {
+                    channel: *channel,
+                    depth: statistics.depth.load(Ordering::SeqCst),
+                    processed,
+                    average_latency: Duration::from_millis(
+                        statistics.total_latency_ms.load(Ordering::SeqCst) / processed.max(1),
+                    ),
+                    max_latency: Duration::from_millis(
+                        statistics.max_latency_ms.load(Ordering::SeqCst),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    fn spawn_worker(self: &Arc<Self>, channel: ChannelId) -> Worker {
+        debug!("starting a worker for channel {}", channel.0);
+
+        let (sender, receiver) = mpsc::channel();
+        let statistics = Arc::new(Statistics::default());
+        let dispatcher = Arc::clone(self);
+        let worker_statistics = Arc::clone(&statistics);
+
+        if let Err(msg) = thread::Builder::new()
+            .name(format!("dispatcher-{}", channel.0))
+            .spawn(move || dispatcher.run_worker(channel, receiver, worker_statistics))
+        {
+            // the sender will fail on the next job, which will try again
+            error!(
+                "unable to start a worker for channel {}: {:?}",
+                channel.0, msg
+            );
+        }
+
+        Worker { sender, statistics }
+    }
+
+    fn run_worker(
+        &self,
+        channel: ChannelId,
+        receiver: mpsc::Receiver<Envelope>,
+        statistics: Arc<Statistics>,
+    ) {
+        loop {
+            let envelope = match receiver.recv_timeout(IDLE_TIMEOUT) {
+                Ok(envelope) => envelope,
+                Err(RecvTimeoutError::Timeout) => {
+                    // new jobs are only sent while the lock is held, so checking for one while
+                    // holding it ensures that nothing is lost when shutting down
+                    let mut workers = self.workers.lock().unwrap();
+                    match receiver.try_recv() {
+                        Ok(envelope) => envelope,
+                        Err(_) => {
+                            debug!("stopping the idle worker for channel {}", channel.0);
+                            workers.remove(&channel);
+                            return;
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            let Envelope {
+                context,
+                job,
+                queued_at,
+            } = envelope;
+
+            let mut attempt = 1;
+            let result = loop {
+                match perform(&context, channel, &job) {
+                    Ok(_) => break Ok(()),
+                    Err(msg) if attempt < MAX_ATTEMPTS && is_transient(&msg) => {
+                        let backoff = INITIAL_BACKOFF * 2u32.pow(attempt - 1);
+                        warn!(
+                            "attempt {} to {} in channel {} failed, retrying in {:?}: {:?}",
+                            attempt,
+                            job.describe(),
+                            channel.0,
+                            backoff,
+                            msg
+                        );
+                        thread::sleep(backoff);
+                        attempt += 1;
+                    }
+                    Err(msg) => break Err(msg),
+                }
+            };
+
+            track(&context, channel, &job, &result);
+            if let Err(msg) = result {
+                error!(
+                    "unable to {} in channel {} after {} attempts: {:?}",
+                    job.describe(),
+                    channel.0,
+                    attempt,
+                    msg
+                );
+
+                // replaying jobs that can never succeed is pointless, so they are dropped
+                if !is_unrecoverable(&msg) {
+                    bury(&context, channel, job, msg.to_string());
+                }
+            }
+
+            let latency = queued_at.elapsed();
+            if latency > SLOW_JOB {
+                warn!(
+                    "a job for channel {} took {:?} to complete",
+                    channel.0, latency
+                );
+            }
+
+            let latency_ms = latency.as_millis() as u64;
+            statistics.depth.fetch_sub(1, Ordering::SeqCst);
+            statistics.processed.fetch_add(1, Ordering::SeqCst);
+            statistics
+                .total_latency_ms
+                .fetch_add(latency_ms, Ordering::SeqCst);
+            statistics
+                .max_latency_ms
+                .fetch_max(latency_ms, Ordering::SeqCst);
+        }
+    }
+}
+
+/// checks if a guild is on one of the shards run by this process
+fn owns(context: &Context, guild_id: u64) -> bool {
+    match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => match (cfg.shard_range, cfg.shards) {
+            (Some((first, last)), Some(shards)) => {
+                let shard = shard_of(guild_id, shards);
+                first <= shard && shard <= last
+            }
+            _ => true,
+        },
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    }
+}
+
+/// computes the shard a guild is on
+fn shard_of(guild_id: u64, shards: u64) -> u64 {
+    (guild_id >> 22) % shards.max(1)
+}
+
+/// checks if a process is receiving the jobs of the shard a guild is on
+fn is_present(context: &Context, guild_id: u64) -> RedisResult<bool> {
+    let shards = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => cfg.shards.unwrap_or(1),
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+    database.exists(presence_key(shard_of(guild_id, shards)))
+}
+
+/// announces that this process is receiving the jobs of its shards
+fn announce(context: &Context) -> RedisResult<()> {
+    let (first, last) = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => match cfg.shard_range {
+            Some(range) => range,
+            None => return Ok(()),
+        },
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+    let mut pipeline = redis::pipe();
+    for shard in first..=last {
+        pipeline
+            .set_ex(presence_key(shard), 1, PRESENCE_TTL)
+            .ignore();
+    }
+    pipeline.query(&mut *database)
+}
+
+/// performs a single job. a database connection is only held around the queries themselves, as
+/// holding one through requests to discord would starve everything else of them
+fn perform(context: &Context, channel: ChannelId, job: &Job) -> SerenityResult<()> {
+    match job {
+        Job::Send {
+            message_id,
+            outgoing,
+        } => {
+            let replica = mirror::send(context, channel, outgoing)?;
+            let retention = match context.data.read().get::<types::Configuration>() {
+                Some(cfg) => cfg.database.mirror_retention,
+                None => panic!(
+                    "no configuration was stored inside of the data TypeMap (this is a severe bug)"
+                ),
+            };
+
+            // the message was still delivered, so this isn't worth retrying over
+            let mut database = get_db_handle!(context.data.read());
+            if let Err(msg) =
+                mirror::record_replica(&mut *database, *message_id, &replica, retention)
+            {
+                error!("unable to record a copy of a mirrored message: {:?}", msg);
+            }
+
+            Ok(())
+        }
+        Job::Edit { replica, outgoing } => mirror::edit(context, replica, outgoing),
+        Job::Delete { replica } => mirror::delete(context, replica),
+    }
+}
+
+/// checks if a job failed in a way that replaying it can't fix, because the channel can't be
+/// used anymore or the copy it was for can't be edited anymore
+fn is_unrecoverable(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Other(msg) => *msg == mirror::WEBHOOK_GONE,
+        _ => is_dead_channel(error),
+    }
+}
+
+/// keeps count of the consecutive deliveries to a channel that failed because it is gone or
+/// inaccessible, unlinking it from its network once there have been too many. only messages
+/// being mirrored count, as edits and deletes can fail for reasons specific to the copy
+fn track(context: &Context, channel: ChannelId, job: &Job, result: &SerenityResult<()>) {
+    if !matches!(job, Job::Send { .. }) {
+        return;
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+
+    let error = match result {
+        Ok(_) => {
+            if let Err(msg) = network::clear_failures(&mut *database, channel.0) {
+                error!(
+                    "unable to reset the failure count of channel {}: {:?}",
+                    channel.0, msg
+                );
+            }
+            return;
+        }
+        Err(error) => error,
+    };
+    if !is_dead_channel(error) {
+        return;
+    }
+
+    let threshold = match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => cfg.unlink_threshold,
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    };
+    let failures = match network::record_failure(&mut *database, channel.0) {
+        Ok(failures) => failures,
+        Err(msg) => {
+            error!(
+                "unable to record a failed delivery to channel {}: {:?}",
+                channel.0, msg
+            );
+            return;
+        }
+    };
+    if failures < threshold {
+        return;
+    }
+
+    warn!(
+        "unlinking channel {} after {} consecutive failed deliveries",
+        channel.0, failures
+    );
+
+    let guild_id = context
+        .cache
+        .read()
+        .guild_channel(channel)
+        .map(|channel| channel.read().guild_id.0);
+    let unlinked = network::unlink_channel(&mut *database, channel.0, guild_id);
+    drop(database);
+    match unlinked {
+        Ok(Some((guild_id, network))) => {
+            network::audit_unlinked(
+                context,
+                channel.0,
+                &network,
+                "deliveries to it kept failing",
+            );
+            network::notify_unlinked(
+                context,
+                guild_id,
+                channel.0,
+                &network,
+                "I am no longer able to send messages to it",
+            );
+        }
+        Ok(None) => (),
+        Err(msg) => error!("unable to unlink channel {}: {:?}", channel.0, msg),
+    }
+}
+
+/// records a job that permanently failed in the dead letter list
+fn bury(context: &Context, channel: ChannelId, job: Job, error: String) {
+    let dead_letter = DeadLetter {
+        channel_id: channel.0,
+        job,
+        error,
+        failed_at: Utc::now().timestamp(),
+    };
+    let encoded = match rmp_serde::to_vec_named(&dead_letter) {
+        Ok(encoded) => encoded,
+        Err(msg) => {
+            error!("unable to encode a dead letter: {:?}", msg);
+            return;
+        }
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+    if let Err(msg) = redis::Script::new(BURY)
+        .key(DEAD_LETTER_COUNTER_KEY)
+        .key(DEAD_LETTERS_KEY)
+        .key(DEAD_LETTER_IDS_KEY)
+        .arg(encoded)
+        .arg(DEAD_LETTER_LIMIT)
+        .invoke::<u64>(&mut *database)
+    {
+        error!("unable to record a dead letter: {:?}", msg);
+    }
+}
+
+fn decode_dead_letter(encoded: &[u8]) -> Option<DeadLetter> {
+    match rmp_serde::from_slice(encoded) {
+        Ok(dead_letter) => Some(dead_letter),
+        Err(msg) => {
+            error!("unable to decode a dead letter: {:?}", msg);
+            None
+        }
+    }
+}
+
+/// retrieves up to `count` dead letters after skipping the given number of them, oldest first,
+/// alongside their ids
+pub fn dead_letters<C: ConnectionLike>(
+    database: &mut C,
+    skip: usize,
+    count: usize,
+) -> RedisResult<Vec<(u64, DeadLetter)>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let ids = database.lrange::<&str, Vec<u64>>(
+        DEAD_LETTER_IDS_KEY,
+        skip as isize,
+        (skip + count - 1) as isize,
+    )?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // dead letters taken since the ids were looked up are skipped
+    let encoded = redis::cmd("HMGET")
+        .arg(DEAD_LETTERS_KEY)
+        .arg(&ids[..])
+        .query::<Vec<Option<Vec<u8>>>>(database)?;
+    Ok(ids
+        .into_iter()
+        .zip(encoded)
+        .filter_map(|(id, encoded)| {
+            decode_dead_letter(&encoded?).map(|dead_letter| (id, dead_letter))
+        })
+        .collect())
+}
+
+/// counts the dead letters
+pub fn count_dead_letters<C: ConnectionLike>(database: &mut C) -> RedisResult<usize> {
+    database.llen(DEAD_LETTER_IDS_KEY)
+}
+
+/// removes a single dead letter and returns it
+pub fn take_dead_letter<C: ConnectionLike>(
+    database: &mut C,
+    id: u64,
+) -> RedisResult<Option<DeadLetter>> {
+    let (encoded,): (Option<Vec<u8>>,) = redis::pipe()
+        .atomic()
+        .hget(DEAD_LETTERS_KEY, id)
+        .hdel(DEAD_LETTERS_KEY, id)
+        .ignore()
+        .lrem(DEAD_LETTER_IDS_KEY, 1, id)
+        .ignore()
+        .query(database)?;
+
+    Ok(encoded.and_then(|encoded| decode_dead_letter(&encoded)))
+}
+
+/// removes every dead letter and returns them, oldest first
+pub fn take_dead_letters<C: ConnectionLike>(database: &mut C) -> RedisResult<Vec<DeadLetter>> {
+    let (ids, mut encoded): (Vec<u64>, HashMap<u64, Vec<u8>>) = redis::pipe()
+        .atomic()
+        .lrange(DEAD_LETTER_IDS_KEY, 0, -1)
+        .hgetall(DEAD_LETTERS_KEY)
+        .del(&[DEAD_LETTER_IDS_KEY, DEAD_LETTERS_KEY])
+        .ignore()
+        .query(database)?;
+
+    Ok(ids
+        .iter()
+        .filter_map(|id| encoded.remove(id))
+        .filter_map(|encoded| decode_dead_letter(&encoded))
+        .collect())
+}
@@ -16,29 +16,229 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use chrono::Utc;
 use log::{debug, error, info};
-use r2d2_redis::redis::{self, Commands};
+use r2d2_redis::redis::Commands;
 use serenity::{
+    client::bridge::gateway::event::ShardStageUpdateEvent,
     http::AttachmentType,
-    model::channel::Message,
+    model::channel::{GuildChannel, Message, Reaction, ReactionType},
+    model::event::MessageUpdateEvent,
     model::gateway::{Activity, Ready},
     model::guild::{Guild, PartialGuild},
-    model::id::{ChannelId, GuildId},
+    model::id::{ChannelId, MessageId},
     prelude::*,
 };
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, collections::HashSet, sync::Arc};
 
-use crate::{get_db_handle, types};
+use crate::{
+    audit,
+    dispatcher::Job,
+    get_db_handle, get_dispatcher, mirror, network, reports,
+    roles::{self, Role},
+    sweeper, types,
+    utils::format_timestamp,
+};
+
+/// how often, in seconds, a muted user is reminded of their mute when they talk
+const MUTE_REMINDER_INTERVAL: usize = 60;
 
 pub struct Handler;
+
+impl Handler {
+    /// deletes every copy of a mirrored message
+    fn unmirror(&self, context: &Context, message_id: MessageId) {
+        let mut database = get_db_handle!(context.data.read());
+
+        let replicas = match mirror::replicas(&mut *database, message_id.0) {
+            Ok(replicas) => replicas,
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the copies of a mirrored message: {:?}",
+                    msg
+                );
+                return;
+            }
+        };
+
+        if replicas.is_empty() {
+            return;
+        }
+
+        // the copies to delete have already been looked up, so the message can be forgotten
+        // before they are, without holding onto the connection while dispatching
+        if let Err(msg) = mirror::forget(&mut *database, message_id.0) {
+            error!(
+                "unable to forget about a deleted mirrored message: {:?}",
+                msg
+            );
+        }
+        drop(database);
+
+        debug!(
+            "propagating the deletion of message {} to {} channels",
+            message_id.0,
+            replicas.len()
+        );
+
+        let dispatcher = get_dispatcher!(context.data.read());
+        for replica in replicas {
+            dispatcher.dispatch(
+                context,
+                ChannelId(replica.channel_id),
+                Job::Delete { replica },
+            );
+        }
+    }
+
+    /// acts on a report when an admin reacts to it with one of its quick actions
+    fn resolve_report(&self, context: &Context, reaction: &Reaction) {
+        let emoji = match &reaction.emoji {
+            ReactionType::Unicode(emoji) => emoji.as_str(),
+            _ => return,
+        };
+        if emoji != reports::BAN_EMOJI && emoji != reports::DISMISS_EMOJI {
+            return;
+        }
+
+        let mut database = get_db_handle!(context.data.read());
+
+        let report_id = match reports::of_post(&mut *database, reaction.message_id.0) {
+            Ok(Some(report_id)) => report_id,
+            Ok(None) => return,
+            Err(msg) => {
+                error!("unable to check if a message is a report: {:?}", msg);
+                return;
+            }
+        };
+
+        match roles::of(&mut *database, reaction.user_id.0) {
+            Ok(role) if role >= Some(Role::Moderator) => (),
+            Ok(_) => return,
+            Err(msg) => {
+                error!("unable to check the role of a user: {:?}", msg);
+                return;
+            }
+        }
+
+        let mut report = match reports::get(&mut *database, report_id) {
+            Ok(Some(report)) if report.status == reports::Status::Open => report,
+            Ok(_) => return,
+            Err(msg) => {
+                error!("unable to retrieve report #{}: {:?}", report_id, msg);
+                return;
+            }
+        };
+
+        let source = audit::Source {
+            actor_id: reaction.user_id.0,
+            guild_id: reaction.guild_id.map(|guild_id| guild_id.0),
+            channel_id: reaction.channel_id.0,
+            message_id: reaction.message_id.0,
+        };
+        let reason = format!("Report #{}: {}", report.id, report.reason);
+
+        let outcome = if emoji == reports::BAN_EMOJI {
+            match roles::of(&mut *database, report.author_id) {
+                Ok(role) if role >= Some(Role::Moderator) => {
+                    if let Err(msg) = reaction
+                        .channel_id
+                        .say(context, "**Error:** You cannot ban a member of staff!")
+                    {
+                        error!("unable to reply to a quick action: {:?}", msg);
+                    }
+                    return;
+                }
+                Ok(_) => (),
+                Err(msg) => {
+                    error!("unable to check the role of a user: {:?}", msg);
+                    return;
+                }
+            }
+
+            let record = network::BanRecord {
+                moderator_id: reaction.user_id.0,
+                guild_id: reaction
+                    .guild_id
+                    .map_or(report.reporter_guild_id, |guild_id| guild_id.0),
+                reason: Some(reason.clone()),
+                banned_at: Utc::now().timestamp(),
+                expires_at: None,
+            };
+            if let Err(msg) =
+                network::ban(&mut *database, &report.network, report.author_id, &record)
+            {
+                error!("unable to ban the author of a report: {:?}", msg);
+                return;
+            }
+
+            report.status = reports::Status::Banned;
+            audit::record(
+                context,
+                &source,
+                &audit::Entry {
+                    action: audit::Action::Ban,
+                    target: Some(audit::Target::User(report.author_id)),
+                    network: Some(&report.network),
+                    reason: Some(&reason),
+                },
+            );
+            format!(
+                "banned <@{}> from the `{}` network",
+                report.author_id, report.network
+            )
+        } else {
+            report.status = reports::Status::Dismissed;
+            audit::record(
+                context,
+                &source,
+                &audit::Entry {
+                    action: audit::Action::Dismiss,
+                    target: Some(audit::Target::User(report.author_id)),
+                    network: Some(&report.network),
+                    reason: Some(&reason),
+                },
+            );
+            String::from("dismissed")
+        };
+
+        if let Err(msg) = reports::save(&mut *database, &report) {
+            error!("unable to update report #{}: {:?}", report.id, msg);
+        }
+
+        if let Err(msg) = reaction.channel_id.say(
+            context,
+            format!(
+                "Report #{} was {} by <@{}>",
+                report.id, outcome, reaction.user_id.0
+            ),
+        ) {
+            error!("unable to reply to a quick action: {:?}", msg);
+        }
+    }
+}
+
 impl EventHandler for Handler {
+    fn reaction_add(&self, context: Context, reaction: Reaction) {
+        self.resolve_report(&context, &reaction);
+    }
+
     fn ready(&self, context: Context, event: Ready) {
         debug!("got ready event: {:?}", event);
-        info!(
-            "logged in as {} on {} servers",
-            event.user.tag(),
-            event.guilds.len(),
-        );
+        match event.shard {
+            Some([id, count]) => info!(
+                "logged in as {} on {} servers with shard {}/{}",
+                event.user.tag(),
+                event.guilds.len(),
+                id + 1,
+                count
+            ),
+            None => info!(
+                "logged in as {} on {} servers",
+                event.user.tag(),
+                event.guilds.len(),
+            ),
+        }
         let data = context.data.read();
         let prefix = match data.get::<types::Configuration>() {
             Some(cfg) => &cfg.prefix,
@@ -49,53 +249,184 @@ impl EventHandler for Handler {
         context.set_activity(Activity::listening(
             format!("your conversations | {}", prefix).as_str(),
         ));
+
+        get_dispatcher!(data).subscribe(&context);
+        sweeper::start(&context);
+
+        // guilds that removed the bot while it was offline never sent a guild_delete event
+        let guilds = event
+            .guilds
+            .iter()
+            .map(|guild| guild.id().0)
+            .collect::<HashSet<u64>>();
+        let mut database = get_db_handle!(data);
+        let reconciliation = network::reconcile(&mut *database, &guilds, event.shard);
+        drop(database);
+        match reconciliation {
+            Ok(reconciliation) => {
+                info!(
+                    "reconciled the database with {} servers. removed {} servers and {} orphaned mirror channels",
+                    guilds.len(),
+                    reconciliation.guilds,
+                    reconciliation.channels.len()
+                );
+                for (channel_id, network) in &reconciliation.unlinked {
+                    network::audit_unlinked(
+                        &context,
+                        *channel_id,
+                        network,
+                        "the bot was removed from its server",
+                    );
+                }
+                for (channel_id, network) in &reconciliation.channels {
+                    network::audit_unlinked(
+                        &context,
+                        *channel_id,
+                        network,
+                        "no server refers to it anymore",
+                    );
+                }
+            }
+            Err(msg) => {
+                error!(
+                    "unable to reconcile the database with the servers the bot is in: {:?}",
+                    msg
+                );
+            }
+        }
     }
 
-    fn guild_delete(&self, context: Context, guild: PartialGuild, _: Option<Arc<RwLock<Guild>>>) {
-        debug!(
-            "the bot has been removed from guild {} updating redis to reflect this",
-            guild.id.0
+    fn shard_stage_update(&self, _: Context, event: ShardStageUpdateEvent) {
+        info!(
+            "shard {} went from {} to {}",
+            event.shard_id.0, event.old, event.new
         );
+    }
 
+    fn guild_create(&self, context: Context, guild: Guild, _: bool) {
         let mut database = get_db_handle!(context.data.read());
 
-        match database.hget::<u64, &str, Option<u64>>(guild.id.0, "mirror_channel") {
-            Ok(chan) => {
-                debug!("retrieved response: {:?}", chan);
-
-                if let Some(chan) = chan {
-                    debug!("found a mirror channel for guild {} at {}", guild.id.0, chan);
+        // the mirror channel may have been deleted while the bot was offline
+        let (channel_id, network) = match network::of_guild(&mut *database, guild.id.0) {
+            Ok(Some(mirror)) => mirror,
+            Ok(None) => return,
+            Err(msg) => {
+                error!(
+                    "unable to check for an existing mirror channel in a guild: {:?}",
+                    msg
+                );
+                return;
+            }
+        };
+        if guild.channels.contains_key(&ChannelId(channel_id)) {
+            return;
+        }
 
-                    // remove the channel from the channels set
-                    match database.srem::<&str, u64, bool>("channels", chan) {
-                        Ok(_) => (),
-                        Err(msg) => {
-                            error!("unable to disable an existing miror channel from the channels set: {:?}", msg);
-                        }
-                    }
-                }
+        info!(
+            "the mirror channel of guild {} at {} no longer exists. unlinking it",
+            guild.id.0, channel_id
+        );
+        let unlinked = network::unlink(&mut *database, guild.id.0);
+        drop(database);
+        match unlinked {
+            Ok(_) => {
+                network::audit_unlinked(&context, channel_id, &network, "it no longer exists");
+                network::notify_unlinked(
+                    &context,
+                    guild.id.0,
+                    channel_id,
+                    &network,
+                    "it no longer exists",
+                );
             }
+            Err(msg) => {
+                error!("unable to unlink a missing mirror channel: {:?}", msg);
+            }
+        }
+    }
+
+    fn channel_delete(&self, context: Context, channel: Arc<RwLock<GuildChannel>>) {
+        let (channel_id, guild_id) = {
+            let channel = channel.read();
+            (channel.id.0, channel.guild_id.0)
+        };
+
+        let mut database = get_db_handle!(context.data.read());
+
+        // unlink only touches the guild if the deleted channel is its mirror channel
+        match network::of_guild(&mut *database, guild_id) {
+            Ok(Some((chan, _))) if chan == channel_id => (),
+            Ok(_) => return,
             Err(msg) => {
                 error!(
-                    "unable to check for an existing mirror channel in a guild: {:?}",
+                    "unable to check if a deleted channel was a mirror channel: {:?}",
                     msg
                 );
+                return;
             }
         }
 
-        // remove the guild from the top-level key-value store
-        match redis::cmd("UNLINK")
-            .arg(guild.id.0)
-            .query::<u64>(&mut (*database))
-        {
-            Ok(_) => (),
+        debug!(
+            "the mirror channel of guild {} was deleted. unlinking it",
+            guild_id
+        );
+
+        let unlinked = network::unlink(&mut *database, guild_id);
+        drop(database);
+        match unlinked {
+            Ok(Some((_, network))) => {
+                network::audit_unlinked(&context, channel_id, &network, "it was deleted");
+                network::notify_unlinked(
+                    &context,
+                    guild_id,
+                    channel_id,
+                    &network,
+                    "it was deleted",
+                );
+            }
+            Ok(None) => (),
+            Err(msg) => {
+                error!("unable to unlink a deleted mirror channel: {:?}", msg);
+            }
+        }
+    }
+
+    fn guild_delete(&self, context: Context, guild: PartialGuild, _: Option<Arc<RwLock<Guild>>>) {
+        debug!(
+            "the bot has been removed from guild {} updating redis to reflect this",
+            guild.id.0
+        );
+
+        let mut database = get_db_handle!(context.data.read());
+
+        match network::unlink(&mut *database, guild.id.0) {
+            Ok(Some((chan, network))) => {
+                debug!(
+                    "removed the mirror channel of guild {} at {} from network {}",
+                    guild.id.0, chan, network
+                );
+                network::audit_unlinked(
+                    &context,
+                    chan,
+                    &network,
+                    "the bot was removed from its server",
+                );
+            }
+            Ok(None) => (),
             Err(msg) => {
                 error!(
-                    "unable to remove a guild's top-level hash from redis: {:?}",
+                    "unable to remove an existing mirror channel from its network: {:?}",
                     msg
                 );
             }
         }
+
+        if let Err(msg) = network::forget_guild(&mut *database, guild.id.0) {
+            error!(
+                "unable to remove the state of a guild from redis: {:?}",
+                msg
+            );
+        }
     }
 
     fn message(&self, context: Context, message: Message) {
@@ -113,33 +444,36 @@ impl EventHandler for Handler {
         }
 
         let mut database = get_db_handle!(context.data.read());
-        let member = match context
-            .http
-            .get_member(message.guild_id.unwrap().0, message.author.id.0)
-        {
-            Ok(member) => member,
-            Err(msg) => {
-                error!(
-                    "unable to get the guild member who sent the message: {:?}",
-                    msg
-                );
-                return;
-            }
+        let retention = match context.data.read().get::<types::Configuration>() {
+            Some(cfg) => cfg.database.mirror_retention,
+            None => panic!(
+                "no configuration was stored inside of the data TypeMap (this is a severe bug)"
+            ),
         };
-
-        match database.sismember::<&str, u64, bool>("channels", message.channel_id.0) {
-            Ok(mirror_chan) => {
-                if !mirror_chan {
+        let network = match network::of_guild(&mut *database, message.guild_id.unwrap().0) {
+            Ok(Some((mirror_chan, network))) => {
+                if mirror_chan != message.channel_id.0 {
                     return;
                 }
+                network
             }
+            Ok(None) => return,
             Err(msg) => {
                 error!("unable to check if the recieved message was sent inside of a mirror channel: {:?}", msg);
                 return;
             }
+        };
+
+        match network::is_guild_banned(&mut *database, &network, message.guild_id.unwrap().0) {
+            Ok(false) => (),
+            Ok(true) => return,
+            Err(msg) => {
+                error!("unable to check if a guild is banned: {:?}", msg);
+                return;
+            }
         }
 
-        match database.sismember::<&str, u64, bool>("banned", message.author.id.0) {
+        match network::is_banned(&mut *database, &network, message.author.id.0) {
             Ok(banned) => {
                 if banned {
                     return;
@@ -151,7 +485,62 @@ impl EventHandler for Handler {
             }
         }
 
-        let display_name = member.display_name();
+        match network::muted_until(&mut *database, &network, message.author.id.0) {
+            Ok(None) => (),
+            Ok(Some(until)) => {
+                // let them know why nobody is answering, without replying to every message
+                match network::remind_muted(
+                    &mut *database,
+                    &network,
+                    message.author.id.0,
+                    MUTE_REMINDER_INTERVAL,
+                ) {
+                    Ok(true) => {
+                        drop(database);
+                        if let Err(msg) = message.channel_id.say(
+                            &context,
+                            format!(
+                                "{}, you are muted in the `{}` network until {}. Your messages are not being mirrored",
+                                message.author.mention(),
+                                network,
+                                format_timestamp(until)
+                            ),
+                        ) {
+                            error!("unable to remind a user of their mute: {:?}", msg);
+                        }
+                    }
+                    Ok(false) => (),
+                    Err(msg) => {
+                        error!("unable to check if a muted user was reminded: {:?}", msg);
+                    }
+                }
+                return;
+            }
+            Err(msg) => {
+                error!("unable to check if a user is muted: {:?}", msg);
+                return;
+            }
+        }
+
+        // discord is only asked about the member once the message is known to need mirroring,
+        // and no connection is held while waiting on it
+        drop(database);
+        let member = match context
+            .http
+            .get_member(message.guild_id.unwrap().0, message.author.id.0)
+        {
+            Ok(member) => member,
+            Err(msg) => {
+                error!(
+                    "unable to get the guild member who sent the message: {:?}",
+                    msg
+                );
+                return;
+            }
+        };
+
+        let display_name = member.display_name().into_owned();
+        let mut database = get_db_handle!(context.data.read());
         let mut key = String::from("usercache-");
         key.push_str(&display_name);
 
@@ -162,36 +551,68 @@ impl EventHandler for Handler {
             }
         }
 
-        let mut content = display_name.to_string();
-        match database.sismember::<&str, u64, bool>("admins", message.author.id.0) {
-            Ok(admin) => {
-                if admin {
-                    content.push_str(" **(__admin__)**: ");
-                } else {
-                    content.push_str(": ");
-                }
-            }
+        // staff and trusted users have their role displayed next to their name
+        let badge = match roles::of(&mut *database, message.author.id.0) {
+            Ok(role) => role.map(|role| String::from(role.name())),
             Err(msg) => {
                 error!(
-                    "unable to check if a user is an admin. assuming they are not: {:?}",
+                    "unable to check the role of a user. assuming they have none: {:?}",
                     msg
                 );
-                content.push_str(": ");
+                None
             }
+        };
+
+        debug!("mirroring message to network {}", network);
+
+        let origin = mirror::Origin {
+            author_id: message.author.id.0,
+            author: display_name.clone(),
+            badge: badge.clone(),
+            guild_id: message.guild_id.unwrap().0,
+            channel_id: message.channel_id.0,
+            network,
+        };
+        if let Err(msg) = mirror::record_origin(&mut *database, message.id.0, &origin, retention) {
+            error!(
+                "unable to record the origin of a mirrored message: {:?}",
+                msg
+            );
         }
 
-        content.push_str(&message.content_safe(&context.cache));
+        let channels = match network::recipients(
+            &mut *database,
+            &origin.network,
+            origin.author_id,
+            origin.guild_id,
+        ) {
+            Ok(channels) => channels,
+            Err(msg) => {
+                error!("unable to retrieve the mirror channels: {:?}", msg);
+                return;
+            }
+        };
 
-        debug!("mirroring message");
+        drop(database);
 
-        let mut files: Vec<AttachmentType> = Vec::with_capacity(message.attachments.len());
+        let mut outgoing = mirror::Outgoing {
+            author: display_name,
+            badge,
+            avatar: message.author.face(),
+            content: message.content_safe(&context.cache),
+            attachment_urls: Vec::with_capacity(message.attachments.len()),
+            files: Vec::with_capacity(message.attachments.len()),
+        };
 
         for attachment in message.attachments {
             match attachment.download() {
-                Ok(data) => files.push(AttachmentType::Bytes {
-                    data: Cow::Owned(data),
-                    filename: attachment.filename.clone(),
-                }),
+                Ok(data) => {
+                    outgoing.attachment_urls.push(attachment.url.clone());
+                    outgoing.files.push(AttachmentType::Bytes {
+                        data: Cow::Owned(data),
+                        filename: attachment.filename.clone(),
+                    });
+                }
                 Err(msg) => {
                     error!("unable to download attachment from discord: {:?}", msg);
                     return;
@@ -199,26 +620,162 @@ impl EventHandler for Handler {
             }
         }
 
-        let channel_iterator = match database.sscan::<&str, u64>("channels") {
-            Ok(iter) => iter,
+        let dispatcher = get_dispatcher!(context.data.read());
+        let outgoing = Arc::new(outgoing);
+        for channel in channels {
+            let channel = ChannelId(channel);
+            if channel == message.channel_id {
+                continue;
+            }
+            dispatcher.dispatch(
+                &context,
+                channel,
+                Job::Send {
+                    message_id: message.id.0,
+                    outgoing: Arc::clone(&outgoing),
+                },
+            );
+        }
+    }
+
+    fn message_update(
+        &self,
+        context: Context,
+        _: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // updates without content are discord filling in embeds, not edits made by the author
+        if event.content.is_none() {
+            return;
+        }
+
+        let mut database = get_db_handle!(context.data.read());
+
+        let origin = match mirror::origin(&mut *database, event.id.0) {
+            Ok(Some(origin)) => origin,
+            Ok(None) => return,
             Err(msg) => {
-                error!("unable to iterate over the mirror channels: {:?}", msg);
+                error!(
+                    "unable to check if an edited message was mirrored: {:?}",
+                    msg
+                );
                 return;
             }
         };
 
-        for channel in channel_iterator {
-            let channel = ChannelId(channel);
-            if channel == message.channel_id {
-                continue;
+        debug!(
+            "propagating an edit of message {} to network {}",
+            event.id.0, origin.network
+        );
+
+        match network::is_guild_banned(&mut *database, &origin.network, origin.guild_id) {
+            Ok(false) => (),
+            Ok(true) => return,
+            Err(msg) => {
+                error!("unable to check if a guild is banned: {:?}", msg);
+                return;
             }
-            if let Err(msg) = channel.send_message(&context, |m| {
-                m.content(&content);
-                m.2 = files.clone();
-                m
-            }) {
-                error!("unable to mirror message to discord: {:?}", msg);
+        }
+
+        match network::is_banned(&mut *database, &origin.network, origin.author_id) {
+            Ok(banned) => {
+                if banned {
+                    return;
+                }
+            }
+            Err(msg) => {
+                error!("unable to check if a user is banned: {:?}", msg);
+                return;
             }
         }
+
+        // editing a message that was mirrored before the mute would get around it
+        match network::muted_until(&mut *database, &origin.network, origin.author_id) {
+            Ok(None) => (),
+            Ok(Some(_)) => return,
+            Err(msg) => {
+                error!("unable to check if a user is muted: {:?}", msg);
+                return;
+            }
+        }
+
+        let replicas = match mirror::replicas(&mut *database, event.id.0) {
+            Ok(replicas) => replicas,
+            Err(msg) => {
+                error!(
+                    "unable to retrieve the copies of a mirrored message: {:?}",
+                    msg
+                );
+                return;
+            }
+        };
+
+        // guilds that have blocked the author or their guild since the message was sent don't
+        // receive the edit either
+        let recipients = match network::recipients(
+            &mut *database,
+            &origin.network,
+            origin.author_id,
+            origin.guild_id,
+        ) {
+            Ok(channels) => channels.into_iter().collect::<HashSet<_>>(),
+            Err(msg) => {
+                error!("unable to retrieve the mirror channels: {:?}", msg);
+                return;
+            }
+        };
+
+        drop(database);
+
+        let message = match new {
+            Some(message) => message,
+            None => match event.channel_id.message(&context, event.id) {
+                Ok(message) => message,
+                Err(msg) => {
+                    error!("unable to retrieve an edited message: {:?}", msg);
+                    return;
+                }
+            },
+        };
+
+        let outgoing = mirror::Outgoing {
+            author: origin.author,
+            badge: origin.badge,
+            avatar: message.author.face(),
+            content: message.content_safe(&context.cache),
+            attachment_urls: message
+                .attachments
+                .iter()
+                .map(|attachment| attachment.url.clone())
+                .collect(),
+            files: Vec::new(),
+        };
+
+        let dispatcher = get_dispatcher!(context.data.read());
+        let outgoing = Arc::new(outgoing);
+        for replica in replicas
+            .into_iter()
+            .filter(|replica| recipients.contains(&replica.channel_id))
+        {
+            dispatcher.dispatch(
+                &context,
+                ChannelId(replica.channel_id),
+                Job::Edit {
+                    replica,
+                    outgoing: Arc::clone(&outgoing),
+                },
+            );
+        }
+    }
+
+    fn message_delete(&self, context: Context, _: ChannelId, message_id: MessageId) {
+        self.unmirror(&context, message_id);
+    }
+
+    fn message_delete_bulk(&self, context: Context, _: ChannelId, message_ids: Vec<MessageId>) {
+        for message_id in message_ids {
+            self.unmirror(&context, message_id);
+        }
     }
 }
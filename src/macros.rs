@@ -44,6 +44,17 @@ macro_rules! get_db_handle {
     };
 }
 
+/// succinctly express that you want to get the dispatcher
+#[macro_export]
+macro_rules! get_dispatcher {
+    ($data:expr) => {
+        match $data.get::<$crate::types::Dispatcher>() {
+            Some(dispatcher) => std::sync::Arc::clone(dispatcher),
+            None => panic!("the dispatcher wasn't initialized and placed into the data TypeMap (this is a severe bug)"),
+        }
+    };
+}
+
 /// simplify sending an error message in the command's channel
 #[macro_export]
 macro_rules! say_error {
@@ -16,8 +16,8 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use log::error;
-use r2d2_redis::{r2d2, redis::Commands, RedisConnectionManager};
+use log::{error, info};
+use r2d2_redis::{r2d2, RedisConnectionManager};
 use ron;
 use serenity::{
     framework::standard::*, model::application::CurrentApplicationInfo, model::id::UserId,
@@ -25,11 +25,22 @@ use serenity::{
 };
 use std::{collections::HashSet, fs, sync::Arc};
 
+mod admins;
+mod appeals;
+mod audit;
 mod colors;
 mod commands;
 mod defaults;
+mod dispatcher;
 mod event_handler;
 mod logger;
+mod mirror;
+mod modlog;
+mod network;
+mod reports;
+mod roles;
+mod strikes;
+mod sweeper;
 mod types;
 mod utils;
 
@@ -43,6 +54,9 @@ fn main() {
             .as_str(),
     )
     .expect("unable to parse configuration");
+    if let Err(msg) = config.validate() {
+        panic!("invalid configuration: {}", msg);
+    }
 
     logger::start_logging(config.log_level, &config.log_file).expect("unable to initiate logging");
 
@@ -69,7 +83,7 @@ fn main() {
     let mut client =
         Client::new(&config.token, event_handler::Handler).expect("unable to initiate client");
 
-    match client.cache_and_http.http.get_current_application_info() {
+    let owner_id = match client.cache_and_http.http.get_current_application_info() {
         Ok(CurrentApplicationInfo { owner, .. }) => {
             admins.insert(owner.id);
             if let Some(admin_ids) = &mut config.admins {
@@ -77,22 +91,33 @@ fn main() {
             } else {
                 config.admins = Some(vec![*owner.id.as_u64()]);
             }
+            owner.id.0
         }
         Err(message) => panic!("unable to get application info: {:?}", message),
     };
 
-    // place the completed admin hashset into redis
+    // make the admins in redis match the configuration, keeping the ones added at runtime
 
     {
         let mut database = database
             .get()
             .expect("unable to get a connection to the redis server from the pool");
-        match database
-            .sadd::<&str, Vec<u64>, u64>("admins", config.admins.as_ref().unwrap().clone())
-        {
-            Ok(_) => (),
+        match admins::reconcile(&mut *database, config.admins.as_ref().unwrap()) {
+            Ok(removed) => {
+                for id in removed {
+                    info!("user {} is no longer an admin, as they were removed from the configuration", id);
+                }
+            }
             Err(msg) => panic!("unable to load admin hashset into redis: {:?}", msg),
         }
+        if let Err(msg) = roles::set_owner(&mut *database, owner_id) {
+            panic!("unable to store the owner in redis: {:?}", msg);
+        }
+
+        // move any single-network state over to the default network
+        if let Err(msg) = network::migrate_legacy(&mut *database) {
+            panic!("unable to migrate legacy mirror state: {:?}", msg);
+        }
     }
 
     client.with_framework(
@@ -103,17 +128,37 @@ fn main() {
             .group(&commands::moderation::MODERATION_GROUP),
     );
 
+    let database = Arc::new(database);
+
+    // the configuration is moved into the data TypeMap below
+    let (shards, shard_range) = (config.shards, config.shard_range);
+
     {
         let mut data = client.data.write();
 
         // make some data available to event handlers & commands
         let _ = data.insert::<types::Configuration>(Arc::new(config));
-        let _ = data.insert::<types::Database>(Arc::new(database));
+        let _ = data.insert::<types::Database>(Arc::clone(&database));
+        let _ = data.insert::<types::Dispatcher>(Arc::new(dispatcher::Dispatcher::default()));
+        let _ = data.insert::<types::ShardManager>(Arc::clone(&client.shard_manager));
     }
 
-    // TODO(superwhiskers): implement sharding support and then switch this to be
-    // "start_autosharded"
-    if let Err(msg) = client.start() {
+    let result = match (shards, shard_range) {
+        (Some(shards), Some((first, last))) => {
+            info!("starting shards {} to {} of {}", first, last, shards);
+            client.start_shard_range([first, last], shards)
+        }
+        (None, Some(_)) => panic!("a shard range was given without the total number of shards"),
+        (Some(shards), None) => {
+            info!("starting {} shards", shards);
+            client.start_shards(shards)
+        }
+        (None, None) => {
+            info!("starting the number of shards recommended by discord");
+            client.start_autosharded()
+        }
+    };
+    if let Err(msg) = result {
         error!("client exited: {:?}", msg);
     }
 }
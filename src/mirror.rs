@@ -0,0 +1,735 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use chrono::Utc;
+use log::{debug, error};
+use r2d2_redis::redis::{self, Commands, ConnectionLike, PipelineCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serenity::{
+    builder::ExecuteWebhook,
+    http::{request::RequestBuilder, routing::RouteInfo, AttachmentType, Http},
+    model::{channel::Channel, id::ChannelId, webhook::Webhook},
+    prelude::*,
+    utils, Error as SerenityError, Result as SerenityResult,
+};
+
+use crate::{get_db_handle, roles::Role, utils::http_status};
+
+/// the name given to the webhooks that the bot creates in mirror channels
+pub const WEBHOOK_NAME: &str = "reflect";
+
+/// the most characters discord accepts in the content of a message
+const MAX_CONTENT_LENGTH: usize = 2000;
+
+/// the most characters discord accepts in the name a webhook posts under
+const MAX_USERNAME_LENGTH: usize = 80;
+
+/// the name used for authors whose name is empty once sanitized
+const FALLBACK_USERNAME: &str = "unknown";
+
+/// shortens text to a number of characters, marking it with an ellipsis if anything was cut
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return String::from(text);
+    }
+
+    let mut truncated = text
+        .chars()
+        .take(limit.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// joins text with a suffix that is kept whole, shortening the text so that the result fits in
+/// a number of characters. suffixes that don't fit by themselves are shortened instead, leaving
+/// the text out
+fn fit(text: &str, suffix: &str, limit: usize) -> String {
+    let room = match limit.checked_sub(suffix.chars().count()) {
+        Some(room) if room > 0 => room,
+        _ => return truncate(suffix, limit),
+    };
+
+    let mut fitted = truncate(text, room);
+    fitted.push_str(suffix);
+    fitted
+}
+
+/// the characters discord uses for formatting, which could be used to dress a name up
+const MARKDOWN: &[char] = &['*', '_', '~', '|', '`'];
+
+/// removes a single badge-like suffix, such as ` (owner)` or ` **[__admin__]**`, from the end of a
+/// name
+fn strip_badge(name: &str) -> Option<&str> {
+    let name = name.trim_end_matches(|c: char| MARKDOWN.contains(&c) || c.is_whitespace());
+    let open = match name.chars().last()? {
+        ')' => '(',
+        ']' => '[',
+        _ => return None,
+    };
+    let start = name.rfind(open)?;
+    let inner = name[start + 1..name.len() - 1]
+        .chars()
+        .filter(|c| !MARKDOWN.contains(c) && !c.is_whitespace())
+        .collect::<String>();
+
+    if Role::ALL
+        .iter()
+        .any(|role| role.name().eq_ignore_ascii_case(&inner))
+    {
+        Some(name[..start].trim_end_matches(|c: char| MARKDOWN.contains(&c)))
+    } else {
+        None
+    }
+}
+
+/// removes anything from the end of a name that looks like a badge, so that users can't pass
+/// themselves off as staff with their nickname
+fn strip_badges(name: &str) -> String {
+    let mut name = name.trim();
+    while let Some(stripped) = strip_badge(name) {
+        name = stripped.trim_end();
+    }
+    String::from(name)
+}
+
+/// breaks up the words discord doesn't allow in the names webhooks post under, and removes
+/// anything that looks like a badge
+fn sanitize_username(name: &str) -> String {
+    let mut name = strip_badges(name);
+    for (word, index, replacement) in &[("discord", 4, "0"), ("clyde", 4, "3")] {
+        // the words are ascii, so their positions in the lowercased name are the same as in the
+        // name itself
+        while let Some(start) = name.to_ascii_lowercase().find(word) {
+            name.replace_range(start + index..start + index + 1, replacement);
+        }
+    }
+    name
+}
+
+/// information about a message that has been mirrored into other guilds
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Origin {
+    pub author_id: u64,
+
+    /// the name that the author was displayed under when the message was mirrored
+    pub author: String,
+
+    /// the badge that was displayed after the author's name, if any
+    pub badge: Option<String>,
+
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub network: String,
+}
+
+/// a copy of a mirrored message in another guild's mirror channel
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replica {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+
+    /// the webhook that sent the copy, if it wasn't sent by the bot itself
+    pub webhook_id: Option<u64>,
+}
+
+/// the key of the value holding the origin of a mirrored message
+fn origin_key(message_id: u64) -> String {
+    format!("mirror-{}", message_id)
+}
+
+/// the key of the hash holding every copy of a mirrored message, keyed by channel
+fn replicas_key(message_id: u64) -> String {
+    format!("mirror-{}-replicas", message_id)
+}
+
+/// the key of the value holding the id of the message that a copy was made from
+fn copy_key(copy_id: u64) -> String {
+    format!("mirror-copy-{}", copy_id)
+}
+
+/// the key of the sorted set indexing every mirrored message sent by a user by the time it was
+/// sent at
+fn author_key(user_id: u64) -> String {
+    format!("mirror-user-{}", user_id)
+}
+
+/// records the origin of a message that is being mirrored, remembering it for `retention`
+/// seconds. the message is also indexed under its author
+pub fn record_origin<C: ConnectionLike>(
+    database: &mut C,
+    message_id: u64,
+    origin: &Origin,
+    retention: usize,
+) -> RedisResult<()> {
+    let encoded = match rmp_serde::to_vec_named(origin) {
+        Ok(encoded) => encoded,
+        Err(msg) => {
+            error!(
+                "unable to encode the origin of a mirrored message: {:?}",
+                msg
+            );
+            return Ok(());
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let author_key = author_key(origin.author_id);
+    redis::pipe()
+        .atomic()
+        .set_ex(origin_key(message_id), encoded, retention)
+        .ignore()
+        .zadd(&author_key, message_id, now)
+        .ignore()
+        .zrembyscore(&author_key, "-inf", now - retention as i64)
+        .ignore()
+        .expire(&author_key, retention)
+        .ignore()
+        .query(database)
+}
+
+/// records a copy of a mirrored message, remembering it for `retention` seconds
+pub fn record_replica<C: ConnectionLike>(
+    database: &mut C,
+    message_id: u64,
+    replica: &Replica,
+    retention: usize,
+) -> RedisResult<()> {
+    let key = replicas_key(message_id);
+    match rmp_serde::to_vec_named(replica) {
+        Ok(encoded) => redis::pipe()
+            .atomic()
+            .hset(&key, replica.channel_id, encoded)
+            .ignore()
+            .expire(&key, retention)
+            .ignore()
+            .set_ex(copy_key(replica.message_id), message_id, retention)
+            .ignore()
+            .query(database),
+        Err(msg) => {
+            error!("unable to encode a copy of a mirrored message: {:?}", msg);
+            Ok(())
+        }
+    }
+}
+
+/// forgets about a mirrored message and its copies, removing it from its author's index as well
+pub fn forget<C: ConnectionLike>(database: &mut C, message_id: u64) -> RedisResult<()> {
+    let origin = origin(database, message_id)?;
+    let mut keys = vec![origin_key(message_id), replicas_key(message_id)];
+    keys.extend(
+        replicas(database, message_id)?
+            .iter()
+            .map(|replica| copy_key(replica.message_id)),
+    );
+
+    let mut pipeline = redis::pipe();
+    pipeline.atomic().del(keys).ignore();
+    if let Some(origin) = origin {
+        pipeline
+            .zrem(author_key(origin.author_id), message_id)
+            .ignore();
+    }
+    pipeline.query(database)
+}
+
+/// retrieves the id of the message that a copy was made from, if it is a copy
+pub fn original_of<C: ConnectionLike>(database: &mut C, copy_id: u64) -> RedisResult<Option<u64>> {
+    database.get(copy_key(copy_id))
+}
+
+/// retrieves the origin of a mirrored message, if it was mirrored
+pub fn origin<C: ConnectionLike>(database: &mut C, message_id: u64) -> RedisResult<Option<Origin>> {
+    Ok(database
+        .get::<String, Option<Vec<u8>>>(origin_key(message_id))?
+        .and_then(|encoded| match rmp_serde::from_slice(&encoded) {
+            Ok(origin) => Some(origin),
+            Err(msg) => {
+                error!(
+                    "unable to decode the origin of a mirrored message: {:?}",
+                    msg
+                );
+                None
+            }
+        }))
+}
+
+/// removes mirrored messages from a user's index
+pub fn unindex<C: ConnectionLike>(
+    database: &mut C,
+    user_id: u64,
+    message_ids: &[u64],
+) -> RedisResult<()> {
+    if message_ids.is_empty() {
+        return Ok(());
+    }
+    database.zrem(author_key(user_id), message_ids)
+}
+
+/// retrieves the ids of the mirrored messages a user has sent, newest first. at most `count`
+/// messages are retrieved, and only those sent since the unix timestamp `since`
+pub fn by_author<C: ConnectionLike>(
+    database: &mut C,
+    user_id: u64,
+    count: Option<isize>,
+    since: Option<i64>,
+) -> RedisResult<Vec<u64>> {
+    let min = match since {
+        Some(since) => since.to_string(),
+        None => String::from("-inf"),
+    };
+
+    database.zrevrangebyscore_limit(author_key(user_id), "+inf", min, 0, count.unwrap_or(-1))
+}
+
+/// retrieves every copy of a mirrored message
+pub fn replicas<C: ConnectionLike>(database: &mut C, message_id: u64) -> RedisResult<Vec<Replica>> {
+    Ok(database
+        .hvals::<String, Vec<Vec<u8>>>(replicas_key(message_id))?
+        .into_iter()
+        .filter_map(|encoded| match rmp_serde::from_slice(&encoded) {
+            Ok(replica) => Some(replica),
+            Err(msg) => {
+                error!("unable to decode a copy of a mirrored message: {:?}", msg);
+                None
+            }
+        })
+        .collect())
+}
+
+/// a message that is about to be mirrored into other guilds' mirror channels
+#[derive(Serialize, Deserialize)]
+pub struct Outgoing {
+    /// the name that the author is displayed under
+    pub author: String,
+
+    /// the badge displayed after the author's name, if any
+    pub badge: Option<String>,
+
+    /// the url of the author's avatar
+    pub avatar: String,
+
+    /// the sanitized content of the message
+    pub content: String,
+
+    /// the urls of the message's attachments. these are linked when mirroring through a webhook
+    pub attachment_urls: Vec<String>,
+
+    /// the message's attachments. these are uploaded when mirroring as the bot itself, and are
+    /// not kept around when a message has to be stored
+    #[serde(skip)]
+    pub files: Vec<AttachmentType<'static>>,
+}
+
+impl Outgoing {
+    /// formats the message for when it is sent as the bot itself, optionally linking the
+    /// attachments
+    fn plain_content(&self, link_attachments: bool) -> String {
+        let mut author = strip_badges(&self.author);
+        if author.is_empty() {
+            author = String::from(FALLBACK_USERNAME);
+        }
+        let content = match &self.badge {
+            Some(badge) => format!("{} **(__{}__)**: {}", author, badge, self.content),
+            None => format!("{}: {}", author, self.content),
+        };
+        if link_attachments {
+            fit(&content, &self.attachment_links(), MAX_CONTENT_LENGTH)
+        } else {
+            truncate(&content, MAX_CONTENT_LENGTH)
+        }
+    }
+
+    /// formats the message for when it is sent through a webhook
+    fn webhook_content(&self) -> String {
+        fit(&self.content, &self.attachment_links(), MAX_CONTENT_LENGTH)
+    }
+
+    /// lists the urls of the attachments, one per line
+    fn attachment_links(&self) -> String {
+        let mut links = String::new();
+        for url in &self.attachment_urls {
+            links.push('\n');
+            links.push_str(url);
+        }
+        links
+    }
+
+    /// formats the name that the webhook posts under, keeping the badge whole
+    fn webhook_username(&self) -> String {
+        let mut author = sanitize_username(&self.author);
+        if author.is_empty() {
+            author = String::from(FALLBACK_USERNAME);
+        }
+        match &self.badge {
+            Some(badge) => fit(&author, &format!(" ({})", badge), MAX_USERNAME_LENGTH),
+            None => truncate(&author, MAX_USERNAME_LENGTH),
+        }
+    }
+}
+
+/// finds the webhook that the bot has created in a channel, creating one if it doesn't exist.
+/// returns nothing if the bot is not permitted to manage webhooks there
+pub fn obtain_webhook(context: &Context, channel_id: ChannelId) -> SerenityResult<Option<Webhook>> {
+    let bot_id = context.cache.read().user.id;
+
+    match channel_id.to_channel(context)? {
+        Channel::Guild(channel) => {
+            if !channel
+                .read()
+                .permissions_for_user(&context.cache, bot_id)?
+                .manage_webhooks()
+            {
+                return Ok(None);
+            }
+        }
+        _ => return Ok(None),
+    }
+
+    for webhook in channel_id.webhooks(&context.http)? {
+        if webhook.token.is_empty() {
+            continue;
+        }
+        if let Some(user) = &webhook.user {
+            if user.id == bot_id {
+                debug!(
+                    "reusing webhook {} in channel {}",
+                    webhook.id.0, channel_id.0
+                );
+                return Ok(Some(webhook));
+            }
+        }
+    }
+
+    debug!("creating a webhook in channel {}", channel_id.0);
+    context
+        .http
+        .create_webhook(channel_id.0, &json!({ "name": WEBHOOK_NAME }))
+        .map(Some)
+}
+
+/// retrieves the id and token of the webhook used to mirror messages into a guild
+fn webhook_of_guild(context: &Context, guild_id: u64) -> Option<(u64, String)> {
+    let mut database = get_db_handle!(context.data.read());
+    match database.hget::<u64, &[&str], (Option<u64>, Option<String>)>(
+        guild_id,
+        &["webhook_id", "webhook_token"],
+    ) {
+        Ok((Some(id), Some(token))) => Some((id, token)),
+        Ok(_) => None,
+        Err(msg) => {
+            error!(
+                "unable to retrieve the webhook of guild {}. falling back to sending as the bot: {:?}",
+                guild_id, msg
+            );
+            None
+        }
+    }
+}
+
+/// mirrors a message into a channel, through the channel's webhook if it has one and as the bot
+/// itself otherwise
+pub fn send(context: &Context, channel: ChannelId, outgoing: &Outgoing) -> SerenityResult<Replica> {
+    let guild_id = match channel.to_channel(context)? {
+        Channel::Guild(channel) => channel.read().guild_id.0,
+        _ => {
+            return Err(SerenityError::Other(
+                "mirror channels must be guild channels",
+            ))
+        }
+    };
+
+    if let Some((webhook_id, webhook_token)) = webhook_of_guild(context, guild_id) {
+        let mut execute_webhook = ExecuteWebhook::default();
+        execute_webhook
+            .username(outgoing.webhook_username())
+            .avatar_url(&outgoing.avatar)
+            .content(outgoing.webhook_content());
+        let map = utils::hashmap_to_json_map(execute_webhook.0);
+
+        match context
+            .http
+            .execute_webhook(webhook_id, &webhook_token, true, &map)
+        {
+            Ok(Some(message)) => {
+                return Ok(Replica {
+                    guild_id,
+                    channel_id: channel.0,
+                    message_id: message.id.0,
+                    webhook_id: Some(webhook_id),
+                })
+            }
+            Ok(None) => {
+                return Err(SerenityError::Other(
+                    "discord did not return the message sent through the webhook",
+                ))
+            }
+            Err(msg) => match http_status(&msg) {
+                // the webhook was deleted from under us, so forget about it
+                Some(404) => {
+                    error!(
+                        "the webhook of guild {} no longer exists. falling back to sending as the bot",
+                        guild_id
+                    );
+                    let mut database = get_db_handle!(context.data.read());
+                    if let Err(msg) = database
+                        .hdel::<u64, &[&str], ()>(guild_id, &["webhook_id", "webhook_token"])
+                    {
+                        error!("unable to remove a stale webhook from redis: {:?}", msg);
+                    }
+                }
+                // discord rejected something about the message, such as a name it doesn't
+                // allow, which won't change by retrying
+                Some(400) => error!(
+                    "the webhook of guild {} rejected a message. falling back to sending as the bot: {:?}",
+                    guild_id, msg
+                ),
+                _ => return Err(msg),
+            },
+        }
+    }
+
+    // attachments that can't be uploaded, such as those of messages that were stored before being
+    // sent, are linked instead
+    let content = outgoing.plain_content(outgoing.files.is_empty());
+
+    let message = channel.send_message(context, |m| {
+        m.content(content);
+        m.2 = outgoing.files.clone();
+        m
+    })?;
+
+    Ok(Replica {
+        guild_id,
+        channel_id: channel.0,
+        message_id: message.id.0,
+        webhook_id: None,
+    })
+}
+
+/// edits a message that was sent through a webhook
+fn edit_webhook_message(
+    http: &Http,
+    webhook_id: u64,
+    token: &str,
+    message_id: u64,
+    content: &str,
+) -> SerenityResult<()> {
+    // serenity has no route for editing webhook messages, but the endpoint lives beneath the
+    // webhook's own route (and shares its ratelimit bucket), so extend that one instead
+    let token = format!("{}/messages/{}", token, message_id);
+    let body = serde_json::to_vec(&json!({ "content": content }))?;
+
+    let mut request = RequestBuilder::new(RouteInfo::EditWebhookWithToken {
+        token: &token,
+        webhook_id,
+    });
+    request.body(Some(&body));
+
+    http.request(request.build()).map(|_| ())
+}
+
+/// the error returned when a copy can't be edited because the webhook that sent it is gone.
+/// only the webhook that sent a message can edit it, so this is permanent
+pub const WEBHOOK_GONE: &str = "the webhook that sent the copy no longer exists";
+
+/// edits a copy of a mirrored message to reflect changes made to the original
+pub fn edit(context: &Context, replica: &Replica, outgoing: &Outgoing) -> SerenityResult<()> {
+    match replica.webhook_id {
+        Some(webhook_id) => match webhook_of_guild(context, replica.guild_id) {
+            Some((current_webhook_id, webhook_token)) if current_webhook_id == webhook_id => {
+                edit_webhook_message(
+                    &context.http,
+                    webhook_id,
+                    &webhook_token,
+                    replica.message_id,
+                    &outgoing.webhook_content(),
+                )
+            }
+            _ => Err(SerenityError::Other(WEBHOOK_GONE)),
+        },
+        None => ChannelId(replica.channel_id)
+            .edit_message(context, replica.message_id, |m| {
+                m.content(outgoing.plain_content(false))
+            })
+            .map(|_| ()),
+    }
+}
+
+/// deletes a copy of a mirrored message
+pub fn delete(context: &Context, replica: &Replica) -> SerenityResult<()> {
+    if let Some(webhook_id) = replica.webhook_id {
+        if let Some((current_webhook_id, webhook_token)) =
+            webhook_of_guild(context, replica.guild_id)
+        {
+            if current_webhook_id == webhook_id {
+                // the same applies here as it does in edit_webhook_message
+                let token = format!("{}/messages/{}", webhook_token, replica.message_id);
+                return context
+                    .http
+                    .request(
+                        RequestBuilder::new(RouteInfo::DeleteWebhookWithToken {
+                            token: &token,
+                            webhook_id,
+                        })
+                        .build(),
+                    )
+                    .map(|_| ());
+            }
+        }
+    }
+
+    // without the webhook's token, deleting it requires the manage messages permission
+    ChannelId(replica.channel_id).delete_message(context, replica.message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_badge_suffixes() {
+        assert_eq!(strip_badges("Bob (owner)"), "Bob");
+        assert_eq!(strip_badges("Bob (Moderator)"), "Bob");
+        assert_eq!(strip_badges("Bob [admin]"), "Bob");
+        assert_eq!(strip_badges("Bob **(__owner__)**"), "Bob");
+        assert_eq!(strip_badges("Bob ( trusted )"), "Bob");
+        assert_eq!(strip_badges("Bob (admin) (owner)"), "Bob");
+    }
+
+    #[test]
+    fn keeps_other_suffixes() {
+        assert_eq!(strip_badges("Bob (he/him)"), "Bob (he/him)");
+        assert_eq!(strip_badges("Bob (owner) fan"), "Bob (owner) fan");
+        assert_eq!(strip_badges("Bob)"), "Bob)");
+        assert_eq!(strip_badges("(owner)"), "");
+    }
+
+    fn outgoing(author: &str, badge: Option<&str>, content: &str, urls: &[&str]) -> Outgoing {
+        Outgoing {
+            author: String::from(author),
+            badge: badge.map(String::from),
+            avatar: String::new(),
+            content: String::from(content),
+            attachment_urls: urls.iter().map(|url| String::from(*url)).collect(),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn truncates_by_characters() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly", 7), "exactly");
+        assert_eq!(truncate("too long", 4), "too…");
+        assert_eq!(truncate("ééééé", 3), "éé…");
+        assert_eq!(truncate("日本語のテキスト", 4), "日本語…");
+        assert_eq!(truncate("anything", 0), "…");
+    }
+
+    #[test]
+    fn fits_suffixes_whole() {
+        assert_eq!(fit("name", " (admin)", 80), "name (admin)");
+        assert_eq!(fit("a long name", " (admin)", 12), "a l… (admin)");
+        assert_eq!(fit("日本語", "!", 3), "日…!");
+        assert_eq!(fit("text", "a suffix that is too long", 5), "a su…");
+        assert_eq!(fit("text", "exact", 5), "exact");
+        assert_eq!(fit("", " (admin)", 80), " (admin)");
+    }
+
+    #[test]
+    fn breaks_up_reserved_words() {
+        assert_eq!(sanitize_username("discord"), "disc0rd");
+        assert_eq!(sanitize_username("DISCORD mod"), "DISC0RD mod");
+        assert_eq!(sanitize_username("DiScOrD"), "DiSc0rD");
+        assert_eq!(sanitize_username("clyde"), "clyd3");
+        assert_eq!(sanitize_username("ClYdE"), "ClYd3");
+        assert_eq!(
+            sanitize_username("discordclydediscord"),
+            "disc0rdclyd3disc0rd"
+        );
+        assert_eq!(sanitize_username("  Bob  "), "Bob");
+    }
+
+    #[test]
+    fn falls_back_for_empty_usernames() {
+        assert_eq!(
+            outgoing("", None, "", &[]).webhook_username(),
+            FALLBACK_USERNAME
+        );
+        assert_eq!(
+            outgoing("   ", None, "", &[]).webhook_username(),
+            FALLBACK_USERNAME
+        );
+        assert_eq!(
+            outgoing(" ", Some("admin"), "", &[]).webhook_username(),
+            format!("{} (admin)", FALLBACK_USERNAME)
+        );
+    }
+
+    #[test]
+    fn limits_usernames() {
+        let long = "a".repeat(100);
+
+        let username = outgoing(&long, None, "", &[]).webhook_username();
+        assert_eq!(username.chars().count(), MAX_USERNAME_LENGTH);
+
+        let username = outgoing(&long, Some("moderator"), "", &[]).webhook_username();
+        assert_eq!(username.chars().count(), MAX_USERNAME_LENGTH);
+        assert!(username.ends_with("… (moderator)"));
+
+        let username = outgoing(&"é".repeat(100), Some("owner"), "", &[]).webhook_username();
+        assert_eq!(username.chars().count(), MAX_USERNAME_LENGTH);
+        assert!(username.ends_with("é… (owner)"));
+    }
+
+    #[test]
+    fn limits_webhook_content_with_attachments() {
+        let urls = ["https://cdn.example/a.png", "https://cdn.example/b.png"];
+        let content = outgoing("Bob", None, &"a".repeat(3000), &urls).webhook_content();
+        assert_eq!(content.chars().count(), MAX_CONTENT_LENGTH);
+        assert!(content.ends_with("…\nhttps://cdn.example/a.png\nhttps://cdn.example/b.png"));
+
+        let content = outgoing("Bob", None, "hi", &urls).webhook_content();
+        assert_eq!(
+            content,
+            "hi\nhttps://cdn.example/a.png\nhttps://cdn.example/b.png"
+        );
+    }
+
+    #[test]
+    fn limits_plain_content() {
+        let urls = ["https://cdn.example/a.png"];
+        let message = outgoing("Bob", Some("admin"), &"日本".repeat(1500), &urls);
+
+        let linked = message.plain_content(true);
+        assert_eq!(linked.chars().count(), MAX_CONTENT_LENGTH);
+        assert!(linked.starts_with("Bob **(__admin__)**: 日本"));
+        assert!(linked.ends_with("…\nhttps://cdn.example/a.png"));
+
+        let unlinked = message.plain_content(false);
+        assert_eq!(unlinked.chars().count(), MAX_CONTENT_LENGTH);
+        assert!(unlinked.ends_with('…'));
+
+        assert_eq!(
+            outgoing("Bob", None, "hi", &urls).plain_content(false),
+            "Bob: hi"
+        );
+    }
+}
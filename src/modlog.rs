@@ -0,0 +1,119 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use chrono::Utc;
+use log::{debug, error};
+use r2d2_redis::redis::Commands;
+use serenity::{
+    model::id::{ChannelId, UserId},
+    prelude::*,
+};
+
+use crate::{
+    admins,
+    audit::{Entry, Source},
+    colors, get_db_handle, network, types,
+};
+
+/// retrieves the global mod-log channel along with the mod-log channel of a network
+pub fn channels(context: &Context, network: Option<&str>) -> Vec<ChannelId> {
+    let mut channels = Vec::new();
+    match context.data.read().get::<types::Configuration>() {
+        Some(cfg) => channels.extend(cfg.mod_log.map(ChannelId)),
+        None => {
+            panic!("no configuration was stored inside of the data TypeMap (this is a severe bug)")
+        }
+    }
+
+    if let Some(network) = network {
+        let mut database = get_db_handle!(context.data.read());
+        match database.hget::<String, &str, Option<u64>>(network::settings_key(network), "modlog") {
+            Ok(channel) => channels.extend(channel.map(ChannelId)),
+            Err(msg) => error!(
+                "unable to retrieve the mod-log channel of network {}: {:?}",
+                network, msg
+            ),
+        }
+    }
+    channels.dedup();
+
+    channels
+}
+
+/// retrieves where things that need the attention of a network's moderators are posted. these
+/// are the network's mod-log channels, or the direct messages of every admin if it has none
+pub fn destinations(context: &Context, network: &str) -> Vec<ChannelId> {
+    let channels = channels(context, Some(network));
+    if !channels.is_empty() {
+        return channels;
+    }
+
+    let mut database = get_db_handle!(context.data.read());
+    match database.smembers::<&str, Vec<u64>>(admins::ADMINS_KEY) {
+        Ok(admins) => admins
+            .into_iter()
+            .filter_map(|admin| match UserId(admin).create_dm_channel(context) {
+                Ok(channel) => Some(channel.id),
+                Err(msg) => {
+                    debug!("unable to message admin {}: {:?}", admin, msg);
+                    None
+                }
+            })
+            .collect(),
+        Err(msg) => {
+            error!("unable to retrieve the admins: {:?}", msg);
+            Vec::new()
+        }
+    }
+}
+
+/// posts a moderation action to the global mod-log channel and to the mod-log channel of the
+/// network it concerns
+pub fn post(context: &Context, source: &Source, entry: &Entry<'_>) {
+    for channel in channels(context, entry.network) {
+        if let Err(msg) = channel.send_message(context, |m| {
+            m.embed(|e| {
+                e.title(format!("Moderation: {}", entry.action))
+                    .field("Moderator", format!("<@{}>", source.actor_id), true)
+                    .field(
+                        "Target",
+                        entry
+                            .target
+                            .map(|target| target.to_string())
+                            .unwrap_or_else(|| String::from("None")),
+                        true,
+                    )
+                    .field("Network", entry.network.unwrap_or("All networks"), true)
+                    .field(
+                        "Reason",
+                        entry.reason.unwrap_or("No reason was given"),
+                        false,
+                    );
+                if let Some(link) = source.link() {
+                    e.field("Message", format!("[Jump to it]({})", link), false);
+                }
+                e.timestamp(&Utc::now()).color(colors::PRIMARY)
+            })
+        }) {
+            error!(
+                "unable to post to the mod-log channel {}: {:?}",
+                channel.0, msg
+            );
+        }
+    }
+}
@@ -0,0 +1,815 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use chrono::Utc;
+use log::{debug, error, info};
+use r2d2_redis::redis::{self, Commands, ConnectionLike, PipelineCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    model::{channel::Message, id::GuildId},
+    prelude::*,
+};
+use std::collections::HashSet;
+
+use crate::{audit, get_db_handle};
+
+/// the network that guilds join when they don't ask for a specific one. guilds that enabled
+/// their mirror channel before networks existed are treated as members of it
+pub const DEFAULT: &str = "global";
+
+/// the longest name a network may have
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// checks if a network name is acceptable. names are limited to lowercase ascii letters,
+/// digits, dashes and underscores so that they can be safely embedded in redis keys
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// the key of the set containing every mirror channel in a network
+pub fn channels_key(network: &str) -> String {
+    format!("network-{}-channels", network)
+}
+
+/// removes a temporary ban if it is still expired when the script runs
+const LIFT_EXPIRED_BAN: &str = r#"
+local expiry = redis.call("ZSCORE", KEYS[1], ARGV[1])
+if expiry and tonumber(expiry) <= tonumber(ARGV[2]) then
+    redis.call("ZREM", KEYS[1], ARGV[1])
+    redis.call("SREM", KEYS[2], ARGV[1])
+    redis.call("HDEL", KEYS[3], ARGV[1])
+    return 1
+end
+return 0
+"#;
+
+/// the key of the hash mapping every mirror channel to the guild it belongs to
+pub const CHANNEL_GUILDS_KEY: &str = "channel-guilds";
+
+/// the key of the hash counting the consecutive failed deliveries to each mirror channel
+const FAILURES_KEY: &str = "channel-failures";
+
+/// the key of the set containing every user banned from a network
+pub fn banned_key(network: &str) -> String {
+    format!("network-{}-banned", network)
+}
+
+/// the key of the sorted set holding when each temporary ban in a network expires, scored by
+/// unix timestamp
+pub fn ban_expiries_key(network: &str) -> String {
+    format!("network-{}-ban-expiries", network)
+}
+
+/// the key of the set containing every guild banned from a network
+pub fn banned_guilds_key(network: &str) -> String {
+    format!("network-{}-banned-guilds", network)
+}
+
+/// the key of the hash holding the record of every guild ban in a network
+pub fn guild_ban_records_key(network: &str) -> String {
+    format!("network-{}-guild-ban-records", network)
+}
+
+/// the key of the hash holding the record of every ban in a network
+pub fn ban_records_key(network: &str) -> String {
+    format!("network-{}-ban-records", network)
+}
+
+/// the key of the sorted set holding when each mute in a network expires, scored by unix
+/// timestamp
+pub fn mutes_key(network: &str) -> String {
+    format!("network-{}-mutes", network)
+}
+
+/// the key of the set containing every guild whose messages aren't mirrored into a guild
+pub fn blocked_guilds_key(guild_id: u64) -> String {
+    format!("guild-{}-blocked-guilds", guild_id)
+}
+
+/// removes the state a guild keeps outside of its network: its top-level hash, which includes
+/// the users it has blocked, and the guilds it has blocked
+pub fn forget_guild<C: ConnectionLike>(database: &mut C, guild_id: u64) -> RedisResult<()> {
+    redis::cmd("UNLINK")
+        .arg(guild_id)
+        .arg(blocked_guilds_key(guild_id))
+        .query(database)
+}
+
+/// the key of the hash containing a network's settings
+pub fn settings_key(network: &str) -> String {
+    format!("network-{}", network)
+}
+
+/// retrieves the mirror channel of a guild along with the network it belongs to
+pub fn of_guild<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+) -> RedisResult<Option<(u64, String)>> {
+    let (channel, network) = database.hget::<u64, &[&str], (Option<u64>, Option<String>)>(
+        guild_id,
+        &["mirror_channel", "network"],
+    )?;
+
+    Ok(channel.map(|channel| (channel, network.unwrap_or_else(|| String::from(DEFAULT)))))
+}
+
+/// removes the mirror channel of a guild from its network, returning the channel and network it
+/// was in
+pub fn unlink<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+) -> RedisResult<Option<(u64, String)>> {
+    let mirror = of_guild(database, guild_id)?;
+
+    if let Some((channel, network)) = &mirror {
+        redis::pipe()
+            .atomic()
+            .srem(channels_key(network), *channel)
+            .ignore()
+            .hdel(
+                guild_id,
+                &["mirror_channel", "network", "webhook_id", "webhook_token"],
+            )
+            .ignore()
+            .hdel(CHANNEL_GUILDS_KEY, *channel)
+            .ignore()
+            .hdel(FAILURES_KEY, *channel)
+            .ignore()
+            .query::<()>(database)?;
+    }
+
+    Ok(mirror)
+}
+
+/// removes a mirror channel from its network, returning the guild and network it was in if
+/// they are known. channels without a known guild are removed from every network
+pub fn unlink_channel<C: ConnectionLike>(
+    database: &mut C,
+    channel_id: u64,
+    guild_id: Option<u64>,
+) -> RedisResult<Option<(u64, String)>> {
+    let guild_id = match guild_id {
+        Some(guild_id) => Some(guild_id),
+        None => database.hget::<&str, u64, Option<u64>>(CHANNEL_GUILDS_KEY, channel_id)?,
+    };
+
+    if let Some(guild_id) = guild_id {
+        if let Some((channel, _)) = of_guild(database, guild_id)? {
+            if channel == channel_id {
+                return Ok(unlink(database, guild_id)?.map(|(_, network)| (guild_id, network)));
+            }
+        }
+    }
+
+    debug!(
+        "unable to find the guild of mirror channel {}. removing it from every network",
+        channel_id
+    );
+    for network in database.smembers::<&str, Vec<String>>("networks")? {
+        database.srem::<String, u64, ()>(channels_key(&network), channel_id)?;
+    }
+    database.hdel::<&str, u64, ()>(CHANNEL_GUILDS_KEY, channel_id)?;
+    database.hdel::<&str, u64, ()>(FAILURES_KEY, channel_id)?;
+
+    Ok(None)
+}
+
+/// records a failed delivery to a mirror channel, returning how many have failed in a row
+pub fn record_failure<C: ConnectionLike>(database: &mut C, channel_id: u64) -> RedisResult<u64> {
+    database.hincr(FAILURES_KEY, channel_id, 1)
+}
+
+/// records a successful delivery to a mirror channel
+pub fn clear_failures<C: ConnectionLike>(database: &mut C, channel_id: u64) -> RedisResult<()> {
+    database.hdel(FAILURES_KEY, channel_id)
+}
+
+/// tells the owner of a guild that its mirror channel has been unlinked, if they can be
+/// messaged
+pub fn notify_unlinked(
+    context: &Context,
+    guild_id: u64,
+    channel_id: u64,
+    network: &str,
+    reason: &str,
+) {
+    let guild = match GuildId(guild_id).to_partial_guild(context) {
+        Ok(guild) => guild,
+        Err(msg) => {
+            debug!(
+                "unable to get guild {} to notify its owner of an unlinked mirror channel: {:?}",
+                guild_id, msg
+            );
+            return;
+        }
+    };
+
+    let result = guild.owner_id.create_dm_channel(context).and_then(|channel| {
+        channel.say(
+            context,
+            format!(
+                "The mirror channel of **{}** (<#{}>) has been removed from the `{}` network because {}. Use the `enable` command to set up a mirror channel again.",
+                guild.name, channel_id, network, reason
+            ),
+        )
+    });
+    if let Err(msg) = result {
+        debug!(
+            "unable to notify the owner of guild {} of an unlinked mirror channel: {:?}",
+            guild_id, msg
+        );
+    }
+}
+
+/// records a mirror channel that was unlinked without anyone asking for it in the audit log
+pub fn audit_unlinked(context: &Context, channel_id: u64, network: &str, reason: &str) {
+    audit::record(
+        context,
+        &audit::Source::automatic(context),
+        &audit::Entry {
+            action: audit::Action::Disable,
+            target: Some(audit::Target::Channel(channel_id)),
+            network: Some(network),
+            reason: Some(reason),
+        },
+    );
+}
+
+/// the prefix of the fields of a guild's hash that mark users blocked by the guild
+const BLOCKED_USER_PREFIX: &str = "blocked-user-";
+
+/// blocks a user's messages from being mirrored into a guild, returning whether they weren't
+/// blocked already
+pub fn block_user<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+    user_id: u64,
+) -> RedisResult<bool> {
+    database.hset_nx(
+        guild_id,
+        format!("{}{}", BLOCKED_USER_PREFIX, user_id),
+        Utc::now().timestamp(),
+    )
+}
+
+/// lets a user's messages be mirrored into a guild again, returning whether they were blocked
+pub fn unblock_user<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+    user_id: u64,
+) -> RedisResult<bool> {
+    database.hdel(guild_id, format!("{}{}", BLOCKED_USER_PREFIX, user_id))
+}
+
+/// retrieves every user blocked by a guild, sorted by id, along with the unix timestamp of when
+/// they were blocked
+pub fn blocked_users<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+) -> RedisResult<Vec<(u64, i64)>> {
+    let mut blocked = database
+        .hgetall::<u64, Vec<(String, String)>>(guild_id)?
+        .into_iter()
+        .filter_map(|(field, blocked_at)| {
+            Some((
+                field.strip_prefix(BLOCKED_USER_PREFIX)?.parse().ok()?,
+                blocked_at.parse().unwrap_or(0),
+            ))
+        })
+        .collect::<Vec<_>>();
+    blocked.sort_unstable();
+    Ok(blocked)
+}
+
+/// blocks a guild's messages from being mirrored into another guild, returning whether it
+/// wasn't blocked already
+pub fn block_guild<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+    blocked_id: u64,
+) -> RedisResult<bool> {
+    database.sadd(blocked_guilds_key(guild_id), blocked_id)
+}
+
+/// lets a guild's messages be mirrored into another guild again, returning whether it was
+/// blocked
+pub fn unblock_guild<C: ConnectionLike>(
+    database: &mut C,
+    guild_id: u64,
+    blocked_id: u64,
+) -> RedisResult<bool> {
+    database.srem(blocked_guilds_key(guild_id), blocked_id)
+}
+
+/// retrieves every guild blocked by a guild, sorted by id
+pub fn blocked_guilds<C: ConnectionLike>(database: &mut C, guild_id: u64) -> RedisResult<Vec<u64>> {
+    let mut blocked = database.smembers::<String, Vec<u64>>(blocked_guilds_key(guild_id))?;
+    blocked.sort_unstable();
+    Ok(blocked)
+}
+
+/// retrieves the mirror channels of a network that a message sent by a user from a guild should
+/// be delivered to, leaving out the channels of guilds that blocked either of them
+pub fn recipients<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    author_id: u64,
+    source_id: u64,
+) -> RedisResult<Vec<u64>> {
+    let channels = database.smembers::<String, Vec<u64>>(channels_key(network))?;
+    if channels.is_empty() {
+        return Ok(channels);
+    }
+
+    // the guild of a channel is only unknown until it is reconciled, and nothing can be
+    // blocked without one
+    let guilds = redis::cmd("HMGET")
+        .arg(CHANNEL_GUILDS_KEY)
+        .arg(&channels[..])
+        .query::<Vec<Option<u64>>>(database)?;
+
+    let field = format!("{}{}", BLOCKED_USER_PREFIX, author_id);
+    let mut pipeline = redis::pipe();
+    for guild in guilds.iter().flatten() {
+        pipeline
+            .hexists(*guild, &field)
+            .sismember(blocked_guilds_key(*guild), source_id);
+    }
+    let mut blocked = pipeline.query::<Vec<bool>>(database)?.into_iter();
+
+    Ok(channels
+        .into_iter()
+        .zip(guilds)
+        .filter(|(_, guild)| {
+            guild.is_none() || {
+                let user_blocked = blocked.next().unwrap_or(false);
+                let guild_blocked = blocked.next().unwrap_or(false);
+                !user_blocked && !guild_blocked
+            }
+        })
+        .map(|(channel, _)| channel)
+        .collect())
+}
+
+/// the details of a ban, kept alongside the user's entry in the network's banned set
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BanRecord {
+    pub moderator_id: u64,
+
+    /// the guild the ban was issued from
+    pub guild_id: u64,
+    pub reason: Option<String>,
+
+    /// the unix timestamp of when the ban was issued
+    pub banned_at: i64,
+
+    /// the unix timestamp of when the ban is lifted, if it is temporary
+    pub expires_at: Option<i64>,
+}
+
+/// bans a user from a network
+pub fn ban<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    record: &BanRecord,
+) -> RedisResult<()> {
+    let mut pipeline = redis::pipe();
+    pipeline
+        .atomic()
+        .sadd(banned_key(network), user_id)
+        .ignore();
+    match record.expires_at {
+        Some(expires_at) => pipeline.zadd(ban_expiries_key(network), user_id, expires_at),
+        None => pipeline.zrem(ban_expiries_key(network), user_id),
+    }
+    .ignore();
+
+    // the ban itself matters more than its record, so it goes through either way
+    match rmp_serde::to_vec_named(record) {
+        Ok(encoded) => pipeline.hset(ban_records_key(network), user_id, encoded),
+        Err(msg) => {
+            error!("unable to encode a ban record: {:?}", msg);
+            pipeline.hdel(ban_records_key(network), user_id)
+        }
+    }
+    .ignore();
+
+    pipeline.query(database)
+}
+
+/// lifts a user's ban from a network, returning whether they were banned
+pub fn unban<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<bool> {
+    let (removed,) = redis::pipe()
+        .atomic()
+        .srem(banned_key(network), user_id)
+        .zrem(ban_expiries_key(network), user_id)
+        .ignore()
+        .hdel(ban_records_key(network), user_id)
+        .ignore()
+        .query::<(bool,)>(database)?;
+    Ok(removed)
+}
+
+fn decode_ban_record(encoded: &[u8]) -> Option<BanRecord> {
+    match rmp_serde::from_slice(encoded) {
+        Ok(record) => Some(record),
+        Err(msg) => {
+            error!("unable to decode a ban record: {:?}", msg);
+            None
+        }
+    }
+}
+
+/// retrieves the record of a user's ban from a network. bans issued before records were kept
+/// don't have one
+pub fn ban_record<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<Option<BanRecord>> {
+    Ok(database
+        .hget::<String, u64, Option<Vec<u8>>>(ban_records_key(network), user_id)?
+        .and_then(|encoded| decode_ban_record(&encoded)))
+}
+
+/// retrieves every user banned from a network, sorted by id, along with the records of their
+/// bans. temporary bans that have expired are left out
+pub fn bans<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+) -> RedisResult<Vec<(u64, Option<BanRecord>)>> {
+    let mut user_ids = database.smembers::<String, Vec<u64>>(banned_key(network))?;
+    user_ids.sort();
+
+    let mut bans = Vec::with_capacity(user_ids.len());
+    for user_id in user_ids {
+        if is_banned(database, network, user_id)? {
+            bans.push((user_id, ban_record(database, network, user_id)?));
+        }
+    }
+
+    Ok(bans)
+}
+
+/// checks if a user is banned from a network. temporary bans that have expired but haven't
+/// been lifted yet are treated as lifted
+pub fn is_banned<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<bool> {
+    let (banned, expiry) = redis::pipe()
+        .sismember(banned_key(network), user_id)
+        .zscore(ban_expiries_key(network), user_id)
+        .query::<(bool, Option<i64>)>(database)?;
+
+    Ok(match expiry {
+        Some(expiry) => banned && expiry > Utc::now().timestamp(),
+        None => banned,
+    })
+}
+
+/// bans a guild from a network. the guild's mirror channel is left alone
+pub fn ban_guild<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    guild_id: u64,
+    record: &BanRecord,
+) -> RedisResult<()> {
+    let mut pipeline = redis::pipe();
+    pipeline
+        .atomic()
+        .sadd(banned_guilds_key(network), guild_id)
+        .ignore();
+
+    match rmp_serde::to_vec_named(record) {
+        Ok(encoded) => pipeline.hset(guild_ban_records_key(network), guild_id, encoded),
+        Err(msg) => {
+            error!("unable to encode a ban record: {:?}", msg);
+            pipeline.hdel(guild_ban_records_key(network), guild_id)
+        }
+    }
+    .ignore();
+
+    pipeline.query(database)
+}
+
+/// lifts a guild's ban from a network, returning whether it was banned
+pub fn unban_guild<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    guild_id: u64,
+) -> RedisResult<bool> {
+    let (removed,) = redis::pipe()
+        .atomic()
+        .srem(banned_guilds_key(network), guild_id)
+        .hdel(guild_ban_records_key(network), guild_id)
+        .ignore()
+        .query::<(bool,)>(database)?;
+    Ok(removed)
+}
+
+/// checks if a guild is banned from a network
+pub fn is_guild_banned<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    guild_id: u64,
+) -> RedisResult<bool> {
+    database.sismember(banned_guilds_key(network), guild_id)
+}
+
+/// retrieves every guild banned from a network, sorted by id, along with the records of their
+/// bans
+pub fn guild_bans<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+) -> RedisResult<Vec<(u64, Option<BanRecord>)>> {
+    let mut guild_ids = database.smembers::<String, Vec<u64>>(banned_guilds_key(network))?;
+    guild_ids.sort();
+
+    let mut bans = Vec::with_capacity(guild_ids.len());
+    for guild_id in guild_ids {
+        let record = database
+            .hget::<String, u64, Option<Vec<u8>>>(guild_ban_records_key(network), guild_id)?
+            .and_then(|encoded| decode_ban_record(&encoded));
+        bans.push((guild_id, record));
+    }
+
+    Ok(bans)
+}
+
+/// lifts every temporary ban that has expired, returning the network and user of each
+pub fn lift_expired_bans<C: ConnectionLike>(database: &mut C) -> RedisResult<Vec<(String, u64)>> {
+    let now = Utc::now().timestamp();
+    let mut lifted = Vec::new();
+
+    for network in database.smembers::<&str, Vec<String>>("networks")? {
+        let expired = database.zrangebyscore::<String, &str, i64, Vec<u64>>(
+            ban_expiries_key(&network),
+            "-inf",
+            now,
+        )?;
+        for user_id in expired {
+            // the ban may have been replaced by a longer one since it was looked up
+            if redis::Script::new(LIFT_EXPIRED_BAN)
+                .key(ban_expiries_key(&network))
+                .key(banned_key(&network))
+                .key(ban_records_key(&network))
+                .arg(user_id)
+                .arg(now)
+                .invoke::<bool>(database)?
+            {
+                lifted.push((network.clone(), user_id));
+            }
+        }
+    }
+
+    Ok(lifted)
+}
+
+/// mutes a user in a network until the given unix timestamp, replacing any existing mute
+pub fn mute<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    until: i64,
+) -> RedisResult<()> {
+    database.zadd(mutes_key(network), user_id, until)
+}
+
+/// unmutes a user in a network, returning whether they were muted
+pub fn unmute<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<bool> {
+    database.zrem(mutes_key(network), user_id)
+}
+
+/// checks if a muted user should be reminded of their mute, which happens at most once per
+/// the given number of seconds
+pub fn remind_muted<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    interval: usize,
+) -> RedisResult<bool> {
+    Ok(redis::cmd("SET")
+        .arg(format!("network-{}-mute-reminder-{}", network, user_id))
+        .arg(1)
+        .arg("EX")
+        .arg(interval)
+        .arg("NX")
+        .query::<Option<String>>(database)?
+        .is_some())
+}
+
+/// retrieves the unix timestamp of when a user's mute in a network expires, if they are muted.
+/// mutes that have expired but haven't been lifted yet are treated as lifted
+pub fn muted_until<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<Option<i64>> {
+    Ok(database
+        .zscore::<String, u64, Option<i64>>(mutes_key(network), user_id)?
+        .filter(|until| *until > Utc::now().timestamp()))
+}
+
+/// lifts every mute that has expired, returning the network and user of each
+pub fn lift_expired_mutes<C: ConnectionLike>(database: &mut C) -> RedisResult<Vec<(String, u64)>> {
+    let now = Utc::now().timestamp();
+    let mut lifted = Vec::new();
+
+    for network in database.smembers::<&str, Vec<String>>("networks")? {
+        let (expired,) = redis::pipe()
+            .atomic()
+            .zrangebyscore(mutes_key(&network), "-inf", now)
+            .zrembyscore(mutes_key(&network), "-inf", now)
+            .ignore()
+            .query::<(Vec<u64>,)>(database)?;
+        lifted.extend(
+            expired
+                .into_iter()
+                .map(|user_id| (network.clone(), user_id)),
+        );
+    }
+
+    Ok(lifted)
+}
+
+/// what was removed by a reconciliation pass
+#[derive(Debug, Default)]
+pub struct Reconciliation {
+    /// guilds the bot is no longer in whose state was removed
+    pub guilds: usize,
+
+    /// the mirror channels of those guilds, along with the networks they were in
+    pub unlinked: Vec<(u64, String)>,
+
+    /// mirror channels that no guild referred to anymore, along with the networks they were in
+    pub channels: Vec<(u64, String)>,
+}
+
+/// removes the state of guilds the bot is no longer in, along with mirror channels that no
+/// guild refers to. when a shard is given, only guilds on that shard are checked, as the
+/// others aren't visible to it
+pub fn reconcile<C: ConnectionLike>(
+    database: &mut C,
+    guilds: &HashSet<u64>,
+    shard: Option<[u64; 2]>,
+) -> RedisResult<Reconciliation> {
+    let mut reconciliation = Reconciliation::default();
+
+    // guild hashes are the only keys that are named after a bare id
+    let keys = database
+        .scan_match::<&str, String>("[0-9]*")?
+        .filter_map(|key| key.parse::<u64>().ok())
+        .collect::<Vec<u64>>();
+
+    let mut linked = HashSet::new();
+    for guild_id in keys {
+        let on_shard = match shard {
+            Some([id, count]) => count == 0 || (guild_id >> 22) % count == id,
+            None => true,
+        };
+
+        if !on_shard || guilds.contains(&guild_id) {
+            if let Some((channel, _)) = of_guild(database, guild_id)? {
+                linked.insert(channel);
+                database.hset::<&str, u64, u64, ()>(CHANNEL_GUILDS_KEY, channel, guild_id)?;
+            }
+            continue;
+        }
+
+        debug!(
+            "removing the state of guild {} as the bot is no longer in it",
+            guild_id
+        );
+        if let Some(mirror) = unlink(database, guild_id)? {
+            reconciliation.unlinked.push(mirror);
+        }
+        forget_guild(database, guild_id)?;
+        reconciliation.guilds += 1;
+    }
+
+    for network in database.smembers::<&str, Vec<String>>("networks")? {
+        for channel in database.smembers::<String, Vec<u64>>(channels_key(&network))? {
+            if linked.contains(&channel) {
+                continue;
+            }
+
+            debug!(
+                "removing channel {} from network {} as no guild refers to it",
+                channel, network
+            );
+            redis::pipe()
+                .atomic()
+                .srem(channels_key(&network), channel)
+                .ignore()
+                .hdel(CHANNEL_GUILDS_KEY, channel)
+                .ignore()
+                .hdel(FAILURES_KEY, channel)
+                .ignore()
+                .query::<()>(database)?;
+            reconciliation.channels.push((channel, network.clone()));
+        }
+    }
+
+    Ok(reconciliation)
+}
+
+/// moves the state left behind by versions of the bot that only had a single mirror network
+/// into the default network
+pub fn migrate_legacy<C: ConnectionLike>(database: &mut C) -> RedisResult<()> {
+    for (legacy, current) in &[
+        ("channels", channels_key(DEFAULT)),
+        ("banned", banned_key(DEFAULT)),
+    ] {
+        if database.exists::<&str, bool>(legacy)? {
+            info!(
+                "migrating the legacy \"{}\" set into \"{}\"",
+                legacy, current
+            );
+            redis::pipe()
+                .atomic()
+                .cmd("SUNIONSTORE")
+                .arg(current)
+                .arg(current)
+                .arg(*legacy)
+                .ignore()
+                .cmd("DEL")
+                .arg(*legacy)
+                .ignore()
+                .query::<()>(database)?;
+        }
+    }
+
+    database.sadd::<&str, &str, ()>("networks", DEFAULT)
+}
+
+/// resolves the network of the guild a command was invoked in
+/// returns an error message if something fails
+pub fn resolve(context: &mut Context, message: &Message) -> Result<String, &'static str> {
+    let guild_id = match message.guild_id {
+        Some(id) => id.0,
+        None => return Err("This command can only be used inside of a server!"),
+    };
+
+    let mut database = get_db_handle!(context.data.read());
+
+    match of_guild(&mut *database, guild_id) {
+        Ok(Some((_, network))) => Ok(network),
+        Ok(None) => Err("This server is not part of a mirror network!"),
+        Err(msg) => {
+            error!("unable to look up the network of a guild: {:?}", msg);
+            Err("Unable to look up the mirror network of this server!")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_simple_names() {
+        assert!(valid_name("main"));
+        assert!(valid_name("dev-chat_2"));
+        assert!(valid_name(&"a".repeat(MAX_NAME_LENGTH)));
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        assert!(!valid_name(""));
+        assert!(!valid_name(&"a".repeat(MAX_NAME_LENGTH + 1)));
+        assert!(!valid_name("Main"));
+        assert!(!valid_name("two words"));
+        assert!(!valid_name("net:work"));
+        assert!(!valid_name("néts"));
+    }
+}
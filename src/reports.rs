@@ -0,0 +1,113 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use log::error;
+use r2d2_redis::redis::{Commands, ConnectionLike, RedisResult};
+use serde::{Deserialize, Serialize};
+
+/// the reaction that bans the author of a reported message
+pub const BAN_EMOJI: &str = "🔨";
+
+/// the reaction that dismisses a report
+pub const DISMISS_EMOJI: &str = "✅";
+
+/// the key of the hash holding every report, keyed by id
+const REPORTS_KEY: &str = "reports";
+
+/// the key of the counter used to number reports
+const NEXT_ID_KEY: &str = "reports-next-id";
+
+/// the key of the hash mapping the messages that reports were posted as to the reports
+const POSTS_KEY: &str = "report-posts";
+
+/// what has been done about a report
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Open,
+    Dismissed,
+    Banned,
+}
+
+/// a mirrored message flagged by a user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Report {
+    pub id: u64,
+    pub reporter_id: u64,
+
+    /// the guild the report was made from
+    pub reporter_guild_id: u64,
+
+    /// the original message, which may not be the copy that was reported
+    pub message_id: u64,
+    pub channel_id: u64,
+    pub guild_id: u64,
+    pub author_id: u64,
+    pub network: String,
+
+    /// the content of the message at the time it was reported, if it could be retrieved
+    pub content: Option<String>,
+    pub reason: String,
+
+    /// the unix timestamp of when the report was made
+    pub created_at: i64,
+    pub status: Status,
+}
+
+/// saves a new report, giving it an id
+pub fn create<C: ConnectionLike>(database: &mut C, report: &mut Report) -> RedisResult<()> {
+    report.id = database.incr(NEXT_ID_KEY, 1)?;
+    save(database, report)
+}
+
+/// saves changes made to a report
+pub fn save<C: ConnectionLike>(database: &mut C, report: &Report) -> RedisResult<()> {
+    match rmp_serde::to_vec_named(report) {
+        Ok(encoded) => database.hset(REPORTS_KEY, report.id, encoded),
+        Err(msg) => {
+            error!("unable to encode a report: {:?}", msg);
+            Ok(())
+        }
+    }
+}
+
+/// retrieves a report by its id
+pub fn get<C: ConnectionLike>(database: &mut C, id: u64) -> RedisResult<Option<Report>> {
+    Ok(database
+        .hget::<&str, u64, Option<Vec<u8>>>(REPORTS_KEY, id)?
+        .and_then(|encoded| match rmp_serde::from_slice(&encoded) {
+            Ok(report) => Some(report),
+            Err(msg) => {
+                error!("unable to decode a report: {:?}", msg);
+                None
+            }
+        }))
+}
+
+/// remembers that a report was posted as a message, so that reactions to it can act on it
+pub fn link_post<C: ConnectionLike>(
+    database: &mut C,
+    post_id: u64,
+    report_id: u64,
+) -> RedisResult<()> {
+    database.hset(POSTS_KEY, post_id, report_id)
+}
+
+/// retrieves the id of the report that was posted as a message, if any
+pub fn of_post<C: ConnectionLike>(database: &mut C, post_id: u64) -> RedisResult<Option<u64>> {
+    database.hget(POSTS_KEY, post_id)
+}
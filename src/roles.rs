@@ -0,0 +1,116 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use r2d2_redis::redis::{self, Commands, ConnectionLike, PipelineCommands, RedisResult};
+use std::fmt;
+
+use crate::admins::ADMINS_KEY;
+
+/// the key holding the id of the owner of the bot
+const OWNER_KEY: &str = "owner";
+
+/// the key of the hash holding the roles given out with the `role` command, keyed by user id.
+/// admins and the owner aren't kept here
+const ROLES_KEY: &str = "roles";
+
+/// how much a user is trusted by the bot, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// users whose messages are marked as trustworthy, without any extra permissions
+    Trusted,
+    Moderator,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    /// every role, from least to most trusted
+    pub const ALL: &'static [Role] = &[Role::Trusted, Role::Moderator, Role::Admin, Role::Owner];
+
+    /// the roles that can be given out with the `role` command
+    pub const ASSIGNABLE: &'static [Role] = &[Role::Trusted, Role::Moderator];
+
+    /// the name of the role, which is also the badge displayed next to the names of its users in
+    /// mirrored messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Trusted => "trusted",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+
+    /// looks an assignable role up by its name
+    pub fn from_name(name: &str) -> Option<Role> {
+        Role::ASSIGNABLE
+            .iter()
+            .copied()
+            .find(|role| role.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+/// remembers who the owner of the bot is
+pub fn set_owner<C: ConnectionLike>(database: &mut C, owner_id: u64) -> RedisResult<()> {
+    database.set(OWNER_KEY, owner_id)
+}
+
+/// retrieves the role of a user, if they have one
+pub fn of<C: ConnectionLike>(database: &mut C, user_id: u64) -> RedisResult<Option<Role>> {
+    let (owner, admin, role) = redis::pipe()
+        .get(OWNER_KEY)
+        .sismember(ADMINS_KEY, user_id)
+        .hget(ROLES_KEY, user_id)
+        .query::<(Option<u64>, bool, Option<String>)>(database)?;
+
+    Ok(if owner == Some(user_id) {
+        Some(Role::Owner)
+    } else if admin {
+        Some(Role::Admin)
+    } else {
+        role.as_deref().and_then(Role::from_name)
+    })
+}
+
+/// gives a user one of the assignable roles, replacing the one they had
+pub fn assign<C: ConnectionLike>(database: &mut C, user_id: u64, role: Role) -> RedisResult<()> {
+    database.hset(ROLES_KEY, user_id, role.name())
+}
+
+/// takes away the assigned role of a user, returning whether they had one
+pub fn unassign<C: ConnectionLike>(database: &mut C, user_id: u64) -> RedisResult<bool> {
+    database.hdel(ROLES_KEY, user_id)
+}
+
+/// retrieves every user with an assigned role, sorted by role from most to least trusted and
+/// then by id
+pub fn assigned<C: ConnectionLike>(database: &mut C) -> RedisResult<Vec<(u64, Role)>> {
+    let mut assigned = database
+        .hgetall::<&str, Vec<(u64, String)>>(ROLES_KEY)?
+        .into_iter()
+        .filter_map(|(user_id, role)| Some((user_id, Role::from_name(&role)?)))
+        .collect::<Vec<_>>();
+    assigned.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    Ok(assigned)
+}
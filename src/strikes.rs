@@ -0,0 +1,242 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use log::error;
+use r2d2_redis::redis::{
+    self, Commands, ConnectionLike, ErrorKind, PipelineCommands, RedisError, RedisResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{types::StrikeConfig, utils::add_duration};
+
+/// a warning issued to a user in a network
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Strike {
+    pub moderator_id: u64,
+
+    /// the guild the warning was issued from
+    pub guild_id: u64,
+    pub reason: String,
+
+    /// the unix timestamp of when the warning was issued
+    pub issued_at: i64,
+}
+
+/// the punishment a user has earned by reaching a number of strikes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escalation {
+    None,
+    Mute {
+        until: i64,
+    },
+
+    /// bans without an expiry are permanent
+    Ban {
+        until: Option<i64>,
+    },
+}
+
+/// works out the harshest punishment earned by reaching a number of strikes at a point in time.
+/// a threshold of zero disables its punishment
+pub fn escalation(config: &StrikeConfig, count: usize, now: i64) -> Escalation {
+    // the durations are validated when the configuration is loaded, so they only overflow if the
+    // clock is wildly off
+    if config.ban_threshold > 0 && count >= config.ban_threshold {
+        Escalation::Ban {
+            until: config
+                .ban_duration
+                .map(|duration| add_duration(now, duration).unwrap_or(i64::MAX)),
+        }
+    } else if config.mute_threshold > 0 && count >= config.mute_threshold {
+        Escalation::Mute {
+            until: add_duration(now, config.mute_duration).unwrap_or(i64::MAX),
+        }
+    } else {
+        Escalation::None
+    }
+}
+
+/// removes the strike at an index if there is one. lists can only have elements removed by value,
+/// so the strike is swapped for a placeholder that is removed instead
+const PARDON: &str = r#"
+if not redis.call("LINDEX", KEYS[1], ARGV[1]) then
+    return 0
+end
+redis.call("LSET", KEYS[1], ARGV[1], "pardoned")
+redis.call("LREM", KEYS[1], 1, "pardoned")
+return 1
+"#;
+
+/// the key of the list holding a user's strikes in a network, oldest first
+fn strikes_key(network: &str, user_id: u64) -> String {
+    format!("network-{}-strikes-{}", network, user_id)
+}
+
+/// gives a user a strike in a network, returning how many they have now
+pub fn add<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    strike: &Strike,
+) -> RedisResult<usize> {
+    let encoded = match rmp_serde::to_vec_named(strike) {
+        Ok(encoded) => encoded,
+        Err(msg) => {
+            error!("unable to encode a strike: {:?}", msg);
+            return Err(RedisError::from((
+                ErrorKind::TypeError,
+                "unable to encode a strike",
+            )));
+        }
+    };
+
+    database.rpush(strikes_key(network, user_id), encoded)
+}
+
+/// retrieves a user's strikes in a network, oldest first, alongside their indices. strikes that
+/// can't be decoded are skipped without affecting the indices of the others
+pub fn list<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<Vec<(usize, Strike)>> {
+    Ok(database
+        .lrange::<String, Vec<Vec<u8>>>(strikes_key(network, user_id), 0, -1)?
+        .iter()
+        .enumerate()
+        .filter_map(|(index, encoded)| match rmp_serde::from_slice(encoded) {
+            Ok(strike) => Some((index, strike)),
+            Err(msg) => {
+                error!("unable to decode a strike: {:?}", msg);
+                None
+            }
+        })
+        .collect())
+}
+
+/// removes one of a user's strikes in a network by its index, returning whether it existed
+pub fn pardon<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+    index: usize,
+) -> RedisResult<bool> {
+    redis::Script::new(PARDON)
+        .key(strikes_key(network, user_id))
+        .arg(index)
+        .invoke(database)
+}
+
+/// removes every one of a user's strikes in a network, returning how many there were
+pub fn pardon_all<C: ConnectionLike>(
+    database: &mut C,
+    network: &str,
+    user_id: u64,
+) -> RedisResult<usize> {
+    let key = strikes_key(network, user_id);
+    let (count,) = redis::pipe()
+        .atomic()
+        .llen(&key)
+        .del(&key)
+        .ignore()
+        .query::<(usize,)>(database)?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        mute_threshold: usize,
+        ban_threshold: usize,
+        ban_duration: Option<u64>,
+    ) -> StrikeConfig {
+        StrikeConfig {
+            mute_threshold,
+            mute_duration: 60,
+            ban_threshold,
+            ban_duration,
+        }
+    }
+
+    #[test]
+    fn nothing_below_the_thresholds() {
+        let config = config(3, 5, None);
+        assert_eq!(escalation(&config, 0, 1000), Escalation::None);
+        assert_eq!(escalation(&config, 2, 1000), Escalation::None);
+    }
+
+    #[test]
+    fn mutes_from_the_mute_threshold() {
+        let config = config(3, 5, None);
+        assert_eq!(
+            escalation(&config, 3, 1000),
+            Escalation::Mute { until: 1060 }
+        );
+        assert_eq!(
+            escalation(&config, 4, 1000),
+            Escalation::Mute { until: 1060 }
+        );
+    }
+
+    #[test]
+    fn bans_from_the_ban_threshold() {
+        assert_eq!(
+            escalation(&config(3, 5, None), 5, 1000),
+            Escalation::Ban { until: None }
+        );
+        assert_eq!(
+            escalation(&config(3, 5, Some(120)), 9, 1000),
+            Escalation::Ban { until: Some(1120) }
+        );
+    }
+
+    #[test]
+    fn bans_take_precedence_over_mutes() {
+        assert_eq!(
+            escalation(&config(3, 3, None), 3, 1000),
+            Escalation::Ban { until: None }
+        );
+    }
+
+    #[test]
+    fn zero_thresholds_are_disabled() {
+        assert_eq!(escalation(&config(0, 0, None), 100, 1000), Escalation::None);
+        assert_eq!(escalation(&config(0, 5, None), 3, 1000), Escalation::None);
+        assert_eq!(
+            escalation(&config(3, 0, None), 100, 1000),
+            Escalation::Mute { until: 1060 }
+        );
+    }
+
+    #[test]
+    fn saturates_instead_of_overflowing() {
+        assert_eq!(
+            escalation(&config(3, 5, None), 3, i64::MAX),
+            Escalation::Mute { until: i64::MAX }
+        );
+        assert_eq!(
+            escalation(&config(3, 5, Some(60)), 5, i64::MAX),
+            Escalation::Ban {
+                until: Some(i64::MAX)
+            }
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//
+//  reflect - a global mirror channel for discord guilds
+//  Copyright (C) superwhiskers <whiskerdev@protonmail.com> 2020
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use log::{error, info};
+use serenity::prelude::*;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::{audit, get_db_handle, network};
+
+/// how often expired punishments are looked for
+const INTERVAL: Duration = Duration::from_secs(30);
+
+/// whether the sweeper is running. every shard becomes ready separately, but only one sweeper
+/// is needed
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// starts a thread that periodically lifts punishments that have expired, recording each in the
+/// audit log. this only does anything the first time it is called
+pub fn start(context: &Context) {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let context = context.clone();
+    if let Err(msg) = thread::Builder::new()
+        .name(String::from("sweeper"))
+        .spawn(move || loop {
+            thread::sleep(INTERVAL);
+
+            // the connection is given back before recording anything, as that takes another
+            let (bans, mutes) = {
+                let mut database = get_db_handle!(context.data.read());
+                (
+                    network::lift_expired_bans(&mut *database),
+                    network::lift_expired_mutes(&mut *database),
+                )
+            };
+
+            match bans {
+                Ok(lifted) => {
+                    for (network, user_id) in lifted {
+                        info!(
+                            "lifted the expired ban of user {} from network {}",
+                            user_id, network
+                        );
+                        record(
+                            &context,
+                            audit::Action::Unban,
+                            &network,
+                            user_id,
+                            "the ban expired",
+                        );
+                    }
+                }
+                Err(msg) => error!("unable to lift expired bans: {:?}", msg),
+            }
+
+            match mutes {
+                Ok(lifted) => {
+                    for (network, user_id) in lifted {
+                        info!(
+                            "lifted the expired mute of user {} in network {}",
+                            user_id, network
+                        );
+                        record(
+                            &context,
+                            audit::Action::Unmute,
+                            &network,
+                            user_id,
+                            "the mute expired",
+                        );
+                    }
+                }
+                Err(msg) => error!("unable to lift expired mutes: {:?}", msg),
+            }
+        })
+    {
+        error!("unable to start the sweeper: {:?}", msg);
+        STARTED.store(false, Ordering::SeqCst);
+    }
+}
+
+/// records a lifted punishment in the audit log
+fn record(context: &Context, action: audit::Action, network: &str, user_id: u64, reason: &str) {
+    audit::record(
+        context,
+        &audit::Source::automatic(context),
+        &audit::Entry {
+            action,
+            target: Some(audit::Target::User(user_id)),
+            network: Some(network),
+            reason: Some(reason),
+        },
+    );
+}
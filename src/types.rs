@@ -19,10 +19,11 @@
 use log::LevelFilter;
 use r2d2_redis::{r2d2::Pool, RedisConnectionManager};
 use serde::Deserialize;
+use serenity::{client::bridge::gateway, prelude::Mutex};
 use std::{fmt, sync::Arc};
 use typemap::Key;
 
-use crate::defaults;
+use crate::{defaults, dispatcher, utils::MAX_DURATION};
 
 /// alias type used for implementing the Deserialize trait on the LevelFilter enum
 #[derive(Deserialize)]
@@ -52,10 +53,58 @@ pub struct Configuration {
 
     pub admins: Option<Vec<u64>>,
 
+    /// the channel every moderation action is posted to. networks can have their own as well
+    pub mod_log: Option<u64>,
+
+    /// how many shards to connect with. the number recommended by discord is used if this is
+    /// left out
+    pub shards: Option<u64>,
+
+    /// the first and last shard run by this process, for when the bot is split across several
+    /// processes. requires `shards` to be set
+    pub shard_range: Option<(u64, u64)>,
+
+    /// how many deliveries to a mirror channel have to fail in a row, because the channel is
+    /// gone or the bot can't access it, before the channel is unlinked
+    #[serde(default = "defaults::unlink_threshold")]
+    pub unlink_threshold: u64,
+
+    #[serde(default = "defaults::strike_configuration")]
+    pub strikes: StrikeConfig,
+
+    /// how many seconds a banned user has to wait between appeals of a ban
+    #[serde(default = "defaults::appeal_cooldown")]
+    pub appeal_cooldown: u64,
+
     #[serde(default = "defaults::database_configuration")]
     pub database: DatabaseConfig,
 }
 
+impl Configuration {
+    /// checks the values whose types allow more than the bot can handle
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.strikes.mute_duration == 0 || self.strikes.mute_duration > MAX_DURATION {
+            return Err("strikes.mute_duration has to be between 1 second and 10 years");
+        }
+        if let Some(ban_duration) = self.strikes.ban_duration {
+            if ban_duration == 0 || ban_duration > MAX_DURATION {
+                return Err("strikes.ban_duration has to be between 1 second and 10 years");
+            }
+        }
+        // redis refuses to expire a key after zero seconds
+        if self.database.mirror_retention == 0
+            || self.database.mirror_retention as u64 > MAX_DURATION
+        {
+            return Err("database.mirror_retention has to be between 1 second and 10 years");
+        }
+        if self.appeal_cooldown == 0 || self.appeal_cooldown > MAX_DURATION {
+            return Err("appeal_cooldown has to be between 1 second and 10 years");
+        }
+
+        Ok(())
+    }
+}
+
 impl fmt::Display for Configuration {
     // TODO(superwhiskers): potentially change this to output it in a different manner. (e.g. not
     // simply being a thin proxy of the Debug trait)
@@ -76,6 +125,35 @@ pub struct DatabaseConfig {
 
     #[serde(default = "defaults::database_max_connections")]
     pub max_connections: u32,
+
+    /// how long, in seconds, the copies of a mirrored message are remembered for. edits and
+    /// deletions of messages older than this are not propagated
+    #[serde(default = "defaults::database_mirror_retention")]
+    pub mirror_retention: usize,
+
+    /// roughly how many entries the audit log keeps. the oldest are dropped first
+    #[serde(default = "defaults::database_audit_retention")]
+    pub audit_retention: usize,
+}
+
+/// a struct used to hold the configuration for when strikes are escalated into punishments. a
+/// threshold of zero disables that punishment
+#[derive(Deserialize, fmt::Debug)]
+pub struct StrikeConfig {
+    /// how many strikes a user needs to be muted
+    #[serde(default = "defaults::strikes_mute_threshold")]
+    pub mute_threshold: usize,
+
+    /// how long, in seconds, those mutes last
+    #[serde(default = "defaults::strikes_mute_duration")]
+    pub mute_duration: u64,
+
+    /// how many strikes a user needs to be banned
+    #[serde(default = "defaults::strikes_ban_threshold")]
+    pub ban_threshold: usize,
+
+    /// how long, in seconds, those bans last. they are permanent if this is left out
+    pub ban_duration: Option<u64>,
 }
 
 /// a struct used to represent the database connection object in the TypeMap
@@ -92,3 +170,18 @@ impl fmt::Display for Database {
 impl Key for Database {
     type Value = Arc<Pool<RedisConnectionManager>>;
 }
+
+/// a struct used to represent the dispatcher in the TypeMap
+#[derive(fmt::Debug)]
+pub struct Dispatcher;
+
+impl Key for Dispatcher {
+    type Value = Arc<dispatcher::Dispatcher>;
+}
+
+/// a struct used to represent the shard manager in the TypeMap
+pub struct ShardManager;
+
+impl Key for ShardManager {
+    type Value = Arc<Mutex<gateway::ShardManager>>;
+}
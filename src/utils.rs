@@ -17,31 +17,35 @@
 //
 
 use crate::get_db_handle;
+use chrono::{DateTime, TimeZone, Utc};
 use log::{debug, error};
 use r2d2_redis::redis::Commands;
-use serenity::{framework::standard::Args, model::channel::Message, prelude::*};
+use serenity::{
+    framework::standard::Args,
+    http::HttpError,
+    model::{channel::Message, id::ChannelId},
+    prelude::*,
+    Error as SerenityError,
+};
+use std::{convert::TryFrom, str::FromStr};
+
+/// the longest duration accepted by commands, which is ten years in seconds
+pub const MAX_DURATION: u64 = 60 * 60 * 24 * 365 * 10;
 
 #[inline(always)]
-fn usercache_lookup(
-    context: &mut Context,
-    _: &Message,
-    arguments: Args,
-) -> Result<Vec<u64>, &'static str> {
-    debug!(
-        "performing a usercache lookup of \"{}\"",
-        arguments.message()
-    );
+fn usercache_lookup(context: &mut Context, name: &str) -> Result<Vec<u64>, &'static str> {
+    debug!("performing a usercache lookup of \"{}\"", name);
 
     let mut database = get_db_handle!(context.data.read());
 
     let mut key = String::from("usercache-");
-    key.push_str(arguments.message());
+    key.push_str(name);
 
     match database.smembers::<&str, Vec<u64>>(&key) {
-        Ok(cache) => return Ok(cache),
+        Ok(cache) => Ok(cache),
         Err(msg) => {
             error!("failed usercache lookup: {}", msg);
-            return Err("Unable to lookup the provided user!");
+            Err("Unable to lookup the provided user!")
         }
     }
 }
@@ -50,7 +54,7 @@ fn usercache_lookup(
 /// returns an error message if something fails
 pub fn resolve_user(
     context: &mut Context,
-    message: &Message,
+    _: &Message,
     arguments: Args,
 ) -> Result<Vec<u64>, &'static str> {
     match arguments.is_empty() {
@@ -65,10 +69,206 @@ pub fn resolve_user(
                     .parse::<u64>()
                 {
                     Ok(parsed_user_id) => Ok(vec![parsed_user_id]),
-                    Err(_) => usercache_lookup(context, message, arguments),
+                    Err(_) => usercache_lookup(context, arguments.message()),
                 },
-                Err(_) => usercache_lookup(context, message, arguments),
+                Err(_) => usercache_lookup(context, arguments.message()),
             },
         },
     }
 }
+
+/// resolves exactly one user from a single argument passed to a command
+/// returns an error message if something fails or if the argument is ambiguous
+pub fn resolve_single_user(context: &mut Context, argument: &str) -> Result<u64, &'static str> {
+    let ids = match argument
+        .trim_end_matches('>')
+        .trim_start_matches("<@")
+        .trim_start_matches('!')
+        .parse::<u64>()
+    {
+        Ok(parsed_user_id) => vec![parsed_user_id],
+        Err(_) => usercache_lookup(context, argument)?,
+    };
+
+    match ids.len() {
+        0 => Err("No user could be found!"),
+        1 => Ok(ids[0]),
+        _ => Err("Usercache listing is not implemented yet!"),
+    }
+}
+
+/// parses a duration such as `90s`, `30m`, `1h30m`, `7d` or `2w` into a number of seconds.
+/// durations longer than `MAX_DURATION` are rejected
+pub fn parse_duration(argument: &str) -> Option<u64> {
+    let mut total: u64 = 0;
+    let mut amount: Option<u64> = None;
+
+    for c in argument.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            amount = Some(
+                amount
+                    .unwrap_or(0)
+                    .checked_mul(10)?
+                    .checked_add(digit as u64)?,
+            );
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            'w' => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        total = total.checked_add(amount?.checked_mul(unit)?)?;
+        amount = None;
+    }
+
+    // a trailing number without a unit is ambiguous, and so is an empty duration
+    if amount.is_some() || total == 0 || total > MAX_DURATION {
+        return None;
+    }
+
+    Some(total)
+}
+
+/// adds a duration in seconds to a unix timestamp, if the result can be represented
+pub fn add_duration(timestamp: i64, duration: u64) -> Option<i64> {
+    timestamp.checked_add(i64::try_from(duration).ok()?)
+}
+
+/// subtracts a duration in seconds from a unix timestamp, if the result can be represented
+pub fn sub_duration(timestamp: i64, duration: u64) -> Option<i64> {
+    timestamp.checked_sub(i64::try_from(duration).ok()?)
+}
+
+/// converts a unix timestamp to a date, if it is within the range chrono can represent
+pub fn datetime(timestamp: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp, 0).single()
+}
+
+/// formats a unix timestamp for humans
+pub fn format_timestamp(timestamp: i64) -> String {
+    match datetime(timestamp) {
+        Some(datetime) => datetime.format("%Y-%m-%d %H:%M UTC").to_string(),
+        None => String::from("an unknown time"),
+    }
+}
+
+/// parses a channel id or a channel mention
+pub fn parse_channel(argument: &str) -> Option<ChannelId> {
+    ChannelId::from_str(argument.trim_end_matches('>').trim_start_matches("<#")).ok()
+}
+
+/// extracts the http status code from an error returned by a request to discord
+pub fn http_status(error: &SerenityError) -> Option<u16> {
+    match error {
+        SerenityError::Http(error) => match &**error {
+            HttpError::UnsuccessfulRequest(response) => Some(response.status_code.as_u16()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// checks if an error returned by a request to discord is likely to go away if the request is
+/// retried later
+pub fn is_transient(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Http(error) => match &**error {
+            HttpError::UnsuccessfulRequest(response) => {
+                let status = response.status_code.as_u16();
+                status == 429 || status >= 500
+            }
+            HttpError::Request(_) => true,
+            _ => false,
+        },
+        SerenityError::Io(_) => true,
+        _ => false,
+    }
+}
+
+/// checks if an error returned by a request to discord means that the channel it was made
+/// against is gone or that the bot can no longer see it. missing permissions aren't included,
+/// as they can be specific to the request, such as deleting a message without manage messages
+pub fn is_dead_channel(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Http(error) => match &**error {
+            // unknown channel and missing access respectively
+            HttpError::UnsuccessfulRequest(response) => {
+                [10003, 50001].contains(&response.error.code)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_units() {
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration("30m"), Some(30 * 60));
+        assert_eq!(parse_duration("2H"), Some(2 * 60 * 60));
+        assert_eq!(parse_duration("7d"), Some(7 * 60 * 60 * 24));
+        assert_eq!(parse_duration("2w"), Some(2 * 60 * 60 * 24 * 7));
+    }
+
+    #[test]
+    fn parses_mixed_units() {
+        assert_eq!(parse_duration("1h30m"), Some(60 * 60 + 30 * 60));
+        assert_eq!(
+            parse_duration("1d1h1m1s"),
+            Some(60 * 60 * 24 + 60 * 60 + 60 + 1)
+        );
+        assert_eq!(parse_duration("10m10m"), Some(20 * 60));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("5y"), None);
+        assert_eq!(parse_duration("-5m"), None);
+    }
+
+    #[test]
+    fn rejects_zero() {
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("0d0h"), None);
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+        assert_eq!(parse_duration("9999999999999999999w"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    }
+
+    #[test]
+    fn caps_durations() {
+        assert_eq!(
+            parse_duration(&format!("{}s", MAX_DURATION)),
+            Some(MAX_DURATION)
+        );
+        assert_eq!(parse_duration(&format!("{}s", MAX_DURATION + 1)), None);
+        assert_eq!(parse_duration("1000w"), None);
+    }
+
+    #[test]
+    fn bounds_timestamp_arithmetic() {
+        assert_eq!(add_duration(100, 50), Some(150));
+        assert_eq!(sub_duration(100, 150), Some(-50));
+        assert_eq!(add_duration(i64::MAX, 1), None);
+        assert_eq!(sub_duration(i64::MIN, 1), None);
+        assert_eq!(add_duration(0, u64::MAX), None);
+        assert_eq!(sub_duration(0, u64::MAX), None);
+    }
+}